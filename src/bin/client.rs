@@ -4,12 +4,10 @@
 // module to the party. We also need TcpListener and
 // TcpStream
 extern crate amf;
-extern crate serde;
 
-use serde::Serialize;
-use amf::{Serializer, Value};
+use amf::Value;
 use std::collections::BTreeMap;
-use std::io::{Read, Write};
+use std::io::Read;
 use std::net::{TcpStream};
 
 fn main() {
@@ -22,14 +20,9 @@ fn main() {
 	map.insert("proprieties3".to_string(), Value::Bool(true));
 	map.insert("proprieties4".to_string(), Value::ECMAArray(tab));
 	let val = Value::Object(map);
-	println!("Envoi de {}", val.to_string());
-	let mut ser = Vec::with_capacity(128);
-	{
-		let serializer = Serializer {writer: &mut ser };
-		let _ = val.serialize(serializer);
-	}
+	println!("Envoi de {}", val);
     let mut stream = TcpStream::connect("127.0.0.1:5432").unwrap();
     let mut response = [0; 128];
     let _ = stream.read(&mut response);
-    let _ = stream.write(&ser);
+    amf::to_writer(&mut stream, &val).unwrap();
 }
@@ -1,20 +1,44 @@
 extern crate byteorder;
 
+use std::convert::TryFrom;
 use std::io;
+use std::marker::PhantomData;
+use std::mem;
 use serde::de;
+use serde::de::value::ValueDeserializer;
 use serde;
-use reader::{Read, SliceReader};
+use reader::{IoReader, Read, SliceReader};
 use error::Error;
 use self::byteorder::{BigEndian, ReadBytesExt};
-use value::Marker;
+use value::{Marker, DATE, LONG_STRING, TYPED_OBJECT, UNDEFINED, VALUE, XML_DOCUMENT};
 
 pub struct Deserializer<W> {
 	pub reader: W,
+	references: Vec<W>, // Readers positioned on each complex value decoded so far
+	resolving: Vec<u16>, // References being resolved, to reject cycles
+	depth: usize,
+	replayed: usize, // Bytes read again to resolve references
 }
 
+/// Deepest nesting of values accepted, deeper input would exhaust the stack.
+const MAX_DEPTH: usize = 128;
+
+/// Most bytes read again to resolve references while decoding a value, as
+/// references to references can make the decoded value grow exponentially.
+/// Each resolved reference also counts for `REFERENCE_COST` bytes, for the
+/// work done besides reading.
+const MAX_REPLAYED: usize = 64 << 20;
+const REFERENCE_COST: usize = 1024;
+
 impl<'a> Deserializer<SliceReader<'a>> {
 	pub fn new_from_slice(slice: &'a [u8]) -> Self {
-		Deserializer{ reader: SliceReader::new(slice) }
+		Deserializer::new(SliceReader::new(slice))
+	}
+}
+
+impl<R: io::BufRead> Deserializer<IoReader<R>> {
+	pub fn new_from_reader(reader: R) -> Self {
+		Deserializer::new(IoReader::new(reader))
 	}
 }
 
@@ -22,187 +46,452 @@ impl<W> Deserializer<W>
 	where W: Read
 {
 	pub fn new(reader: W) -> Self {
-		Deserializer{ reader: reader }
+		Deserializer{ reader, references: Vec::new(), resolving: Vec::new(), depth: 0, replayed: 0 }
+	}
+
+	/// Iterate over the values written back to back in the input.
+	pub fn into_stream<T: de::Deserialize>(self) -> StreamDeserializer<W, T> {
+		StreamDeserializer {
+			de: self,
+			failed: false,
+			marker: PhantomData,
+		}
+	}
+
+	/// Read a reference index and decode the complex value it points to with
+	/// `f`. The value is decoded again from its bytes, so each reference
+	/// yields its own copy. A value can't reference one of its parents, as
+	/// this would be an endless tree.
+	fn parse_reference<F, V>(&mut self, f: F) -> Result<V, self::Error>
+		where F: FnOnce(&mut Deserializer<W>) -> Result<V, self::Error>
+	{
+		let index = self.read_u16()?;
+		if index as usize >= self.references.len() || self.resolving.contains(&index) {
+			return Err(Error::InvalidReference(index));
+		}
+		if self.replayed > MAX_REPLAYED {
+			return Err(Error::ReferenceLimitExceeded);
+		}
+		let mut resolving = self.resolving.clone();
+		resolving.push(index);
+		// The replay sees the values before the referenced one, as when it was first decoded
+		let later = self.references.split_off(index as usize);
+		let mut de = Deserializer {
+			reader: later[0].copy(),
+			references: mem::take(&mut self.references),
+			resolving,
+			depth: self.depth,
+			replayed: self.replayed,
+		};
+		let start = de.reader.offset();
+		let value = f(&mut de);
+		self.replayed = de.replayed + (de.reader.offset() - start) + REFERENCE_COST;
+		self.references = de.references;
+		self.references.truncate(index as usize);
+		self.references.extend(later);
+		value
+	}
+
+	/// Run `f` one level deeper, failing beyond the maximum depth.
+	fn nested<F, V>(&mut self, f: F) -> Result<V, self::Error>
+		where F: FnOnce(&mut Deserializer<W>) -> Result<V, self::Error>
+	{
+		if self.depth >= MAX_DEPTH {
+			return Err(Error::DepthLimitExceeded);
+		}
+		self.depth += 1;
+		let value = f(self);
+		self.depth -= 1;
+		if self.depth == 0 && self.resolving.is_empty() {
+			// Each top-level value has its own reference table and replay
+			// budget, and the readers it holds would keep the bytes read so far
+			self.references.clear();
+			self.replayed = 0;
+		}
+		value
+	}
+
+	fn eof(&self) -> Error {
+		Error::UnexpectedEOF(self.reader.offset())
+	}
+
+	/// Read a Number if it is the next value.
+	fn parse_number(&mut self) -> Result<Option<f64>, self::Error> {
+		match self.peek_marker()? {
+			Some(Marker::Number) => {
+				self.reader.discard();
+				self.read_f64().map(Some)
+			},
+			_ => Ok(None),
+		}
 	}
 
 	fn read_marker(&mut self) -> Result<Marker, self::Error>
 	{
-		match try!(self.reader.next()) {
-			None => Err(Error::UnexpectedEOF),
-			Some(c) => Ok(Marker::from(c)),
+		let marker = self.peek_marker()?.ok_or_else(|| self.eof())?;
+		self.reader.discard();
+		Ok(marker)
+	}
+
+	fn peek_marker(&mut self) -> Result<Option<Marker>, self::Error>
+	{
+		match self.reader.peek()? {
+			None => Ok(None),
+			Some(c) => Marker::try_from(c)
+				.map(Some)
+				.map_err(|marker| Error::UnknownMarker{marker, offset: self.reader.offset()}),
+		}
+	}
+
+	/// Check that the whole input has been read.
+	pub fn end(&mut self) -> Result<(), self::Error> {
+		match self.reader.peek()? {
+			None => Ok(()),
+			Some(_) => Err(Error::TrailingBytes(self.reader.offset())),
 		}
 	}
 
 	fn next_value_or_eof(&mut self) -> Result<u8, self::Error>
 	{
-		match try!(self.reader.next()) {
-			None => Err(Error::UnexpectedEOF),
+		match self.reader.next()? {
+			None => Err(self.eof()),
 			Some(c) => Ok(c),
 		}
 	}
 
 	fn parse_string(&mut self) -> Result<String, self::Error> {
-		let mut tab = Vec::new();
-		for _ in 0..2 {
-			let c = try!(self.next_value_or_eof());
-			tab.push(c);
-		}
-		let mut c = io::Cursor::new(tab);
-		let nb = c.read_u16::<BigEndian>().unwrap();
+		let nb = self.read_u16()?;
+		self.parse_utf8(nb as u32)
+	}
+
+	fn parse_long_string(&mut self) -> Result<String, self::Error> {
+		let nb = self.read_u32()?;
+		self.parse_utf8(nb)
+	}
+
+	fn parse_utf8(&mut self, nb: u32) -> Result<String, self::Error> {
+		let offset = self.reader.offset();
 		let mut str = Vec::new();
 		for _ in 0..nb {
-			match try!(self.reader.next()) {
-				None => return Err(Error::InvalidSize),
-				Some(c) => str.push(c),
-			}
+			let c = self.next_value_or_eof()?;
+			str.push(c);
 		}
-		Ok(String::from_utf8(str).unwrap())
+		String::from_utf8(str).map_err(|_| Error::InvalidUtf8(offset))
 	}
 
-	fn parse_string_or_end(&mut self) -> Option<String> {
-		let mut tab = Vec::new();
-		for _ in 0..2 {
-			tab.push(self.reader.next().unwrap().unwrap());
-		}
-		let mut c = io::Cursor::new(tab);
-		let nb = c.read_u16::<BigEndian>().unwrap();
-		if nb == 0
-		{
-			match self.reader.next().unwrap() {
-				Some(0x09) => None,
-				_ => None //Error
+	/// Read an object key, or None at the end of the object.
+	fn parse_key(&mut self) -> Result<Option<String>, self::Error> {
+		let nb = self.read_u16()?;
+		if nb == 0 {
+			match self.reader.peek()? {
+				Some(0x09) => {
+					self.reader.discard();
+					Ok(None)
+				},
+				Some(_) => Ok(Some(String::new())),
+				None => Err(self.eof()),
 			}
+		} else {
+			self.parse_utf8(nb as u32).map(Some)
 		}
-		else {
-			let mut str = Vec::new();
-			for _ in 0..nb {
-				str.push(self.reader.next().unwrap().unwrap());
+	}
+
+	fn parse_object_end(&mut self) -> Result<(), self::Error> {
+		for expected in &[0x00, 0x00, 0x09] {
+			if self.next_value_or_eof()? != *expected {
+				return Err(Error::SyntaxError);
 			}
-			Some(String::from_utf8(str).unwrap())
 		}
+		Ok(())
 	}
 
 	fn read_u32(&mut self) -> Result<u32, self::Error>
 	{
 		let mut tab = Vec::new();
 		for _ in 0..4 {
-			let c = try!(self.next_value_or_eof());
+			let c = self.next_value_or_eof()?;
 			tab.push(c);
 		}
 		let mut c = io::Cursor::new(tab);
-		let nb = try!(c.read_u32::<BigEndian>());
+		let nb = c.read_u32::<BigEndian>()?;
 		Ok(nb)
 	}
 
-	fn parse_value<T: de::Visitor>(&mut self, visitor: T) -> Result<T::Value, self::Error> {
-		let c = try!(self.read_marker());
+	fn read_u16(&mut self) -> Result<u16, self::Error>
+	{
+		let mut tab = Vec::new();
+		for _ in 0..2 {
+			let c = self.next_value_or_eof()?;
+			tab.push(c);
+		}
+		let mut c = io::Cursor::new(tab);
+		let nb = c.read_u16::<BigEndian>()?;
+		Ok(nb)
+	}
+
+	fn read_i16(&mut self) -> Result<i16, self::Error>
+	{
+		let mut tab = Vec::new();
+		for _ in 0..2 {
+			let c = self.next_value_or_eof()?;
+			tab.push(c);
+		}
+		let mut c = io::Cursor::new(tab);
+		let nb = c.read_i16::<BigEndian>()?;
+		Ok(nb)
+	}
+
+	fn read_f64(&mut self) -> Result<f64, self::Error>
+	{
+		let mut tab = Vec::new();
+		for _ in 0..8 {
+			let c = self.next_value_or_eof()?;
+			tab.push(c);
+		}
+		let mut c = io::Cursor::new(tab);
+		let nb = c.read_f64::<BigEndian>()?;
+		Ok(nb)
+	}
+
+	/// When `tagged` is set, types that have no equivalent in the serde data
+	/// model are handed to the visitor as an enum variant named after the
+	/// AMF type. This is how `Value` keeps them apart from their plain
+	/// counterparts.
+	fn parse_value<T: de::Visitor>(&mut self, visitor: T, tagged: bool) -> Result<T::Value, self::Error> {
+		self.nested(|de| de.parse_marker(visitor, tagged))
+	}
+
+	fn parse_marker<T: de::Visitor>(&mut self, visitor: T, tagged: bool) -> Result<T::Value, self::Error> {
+		let start = self.reader.copy();
+		let offset = start.offset();
+		let c = self.read_marker()?;
+		match c {
+			Marker::Object | Marker::TypedObject | Marker::ECMAArray | Marker::StrictArray => self.references.push(start),
+			_ => {},
+		}
 		match c {
 			Marker::Number => {
-				let mut tab = Vec::new();
-				for _ in 0..8 {
-					let c = try!(self.next_value_or_eof());
-					tab.push(c);
-				}
-				let mut c = io::Cursor::new(tab);
-				let nb = c.read_f64::<BigEndian>().unwrap();
+				let nb = self.read_f64()?;
 				visitor.visit_f64(nb)
 			},
 			Marker::Boolean => {
-				let c = try!(self.next_value_or_eof());
-				let tab = vec![c];
-				let mut cursor = io::Cursor::new(tab);
-				let b = cursor.read_u8().unwrap();
+				let b = self.next_value_or_eof()?;
 				visitor.visit_bool(b != 0)						
 			},
 			Marker::String => {
-				let s = try!(self.parse_string());
+				let s = self.parse_string()?;
 				visitor.visit_string(s)
 			},
+			Marker::LongString => {
+				let s = self.parse_long_string()?;
+				if tagged {
+					visitor.visit_enum(TaggedVisitor::new("LongString", ValueDeserializer::<Error>::into_deserializer(s)))
+				} else {
+					visitor.visit_string(s)
+				}
+			},
+			Marker::Date => {
+				let date = self.read_f64()?;
+				let time_zone = self.read_i16()?;
+				if tagged {
+					visitor.visit_enum(TaggedVisitor::new("Date", DateDeserializer::new(date, time_zone)))
+				} else {
+					visitor.visit_f64(date)
+				}
+			},
+			Marker::XMLDocument => {
+				let s = self.parse_long_string()?;
+				if tagged {
+					visitor.visit_enum(TaggedVisitor::new("XMLDocument", ValueDeserializer::<Error>::into_deserializer(s)))
+				} else {
+					visitor.visit_string(s)
+				}
+			},
+			Marker::TypedObject => {
+				let class_name = self.parse_string()?;
+				if tagged {
+					visitor.visit_enum(TaggedVisitor::new("TypedObject", TypedObjectDeserializer::new(self, class_name)))
+				} else {
+					visitor.visit_map(MapVisitor::new(self, None))
+				}
+			},
 			Marker::Object => {
                 visitor.visit_map(MapVisitor::new(self, None))
 			}
 			Marker::ECMAArray => {
-				let nb = try!(self.read_u32());
+				let nb = self.read_u32()?;
                 visitor.visit_map(MapVisitor::new(self, Some(nb)))
 			}
 			Marker::StrictArray => {
-				let nb = try!(self.read_u32());
+				let nb = self.read_u32()?;
                 visitor.visit_seq(SeqVisitor::new(self, nb))
 			}
 			Marker::Undefined => {
 				visitor.visit_none()
 			}
-			_ => visitor.visit_unit()
+			Marker::Reference => {
+				self.parse_reference(|de| de.parse_value(visitor, tagged))
+			}
+			Marker::Unsupported => {
+				visitor.visit_none() // Written in place of a value the peer couldn't encode
+			}
+			Marker::Null => visitor.visit_unit(),
+			Marker::Movieclip => Err(Error::UnknownMarker{marker: 0x04, offset}),
+			Marker::ObjectEnd => Err(Error::UnknownMarker{marker: 0x09, offset}),
+			Marker::Recordset => Err(Error::UnknownMarker{marker: 0x0E, offset}),
+		}
+	}
+}
+
+/// Check that a Number holds an integer within `min..max + 1`.
+fn integer(v: f64, min: f64, max: f64) -> Result<f64, self::Error> {
+	if !v.is_finite() || v < min || v >= max + 1. {
+		Err(Error::IntegerOutOfRange(v))
+	} else if v.fract() != 0. {
+		Err(Error::IntegerWithFraction(v))
+	} else {
+		Ok(v)
+	}
+}
+
+/// Numbers are read into integer targets when they hold an integer in range.
+macro_rules! deserialize_integer {
+	($($method:ident => $visit:ident($ty:ident),)*) => {
+		$(
+		fn $method<T: de::Visitor>(self, visitor: T) -> Result<T::Value, self::Error> {
+			match self.parse_number()? {
+				Some(v) => visitor.$visit(integer(v, $ty::MIN as f64, $ty::MAX as f64)? as $ty),
+				None => self.parse_value(visitor, false),
+			}
 		}
+		)*
 	}
 }
 
-impl<'a, W> serde::Deserializer for &'a mut Deserializer<W>
+impl<W> serde::Deserializer for &mut Deserializer<W>
 	where W: Read,
 {
 
 	type Error = Error;
 
 	fn deserialize<T: de::Visitor>(self, visitor : T) -> Result<T::Value, self::Error> {
-		self.parse_value(visitor)
+		self.parse_value(visitor, false)
 	}
 
-	forward_to_deserialize! {
-        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char str string
-        unit option seq seq_fixed_size bytes byte_buf map unit_struct
-        newtype_struct tuple_struct struct struct_field tuple enum ignored_any
-	}	
-}
+	/// Accept the enum layout written by the serializer: a string for unit
+	/// variants, or an object with a single key naming the variant.
+	fn deserialize_enum<T: de::Visitor>(self, name: &'static str, variants: &'static [&'static str], visitor: T) -> Result<T::Value, self::Error> {
+		match self.peek_marker()? {
+			Some(Marker::String) => {
+				self.reader.discard();
+				let variant = self.parse_string()?;
+				visitor.visit_enum(ValueDeserializer::<Error>::into_deserializer(variant))
+			},
+			Some(Marker::Object) => {
+				self.references.push(self.reader.copy());
+				self.reader.discard();
+				self.nested(|de| visitor.visit_enum(VariantVisitor::new(de)))
+			},
+			Some(Marker::Reference) => {
+				self.reader.discard();
+				self.parse_reference(|de| de.deserialize_enum(name, variants, visitor))
+			},
+			Some(_) => Err(Error::SyntaxError),
+			None => Err(self.eof()),
+		}
+	}
 
-pub struct StringDeserializer<W> {
-	pub reader: W,
-}
+	/// Newtype structs are transparent, the bare inner value is expected.
+	fn deserialize_newtype_struct<T: de::Visitor>(self, name: &'static str, visitor: T) -> Result<T::Value, self::Error> {
+		match name {
+			VALUE | DATE | LONG_STRING | XML_DOCUMENT | TYPED_OBJECT | UNDEFINED => self.parse_value(visitor, true),
+			_ => visitor.visit_newtype_struct(self),
+		}
+	}
 
-impl<W> StringDeserializer<W>
-	where W: Read
-{
-	fn next_value_or_eof(&mut self) -> Result<u8, self::Error>
-	{
-		match try!(self.reader.next()) {
-			None => Err(Error::UnexpectedEOF),
-			Some(c) => Ok(c),
+	/// Structs are read from any object, whatever its class name, or from
+	/// a strict array holding the fields in order.
+	fn deserialize_struct<T: de::Visitor>(self, name: &'static str, fields: &'static [&'static str], visitor: T) -> Result<T::Value, self::Error> {
+		match self.peek_marker()? {
+			Some(Marker::Object) | Some(Marker::TypedObject) | Some(Marker::ECMAArray) | Some(Marker::StrictArray) => self.parse_value(visitor, false),
+			Some(Marker::Reference) => {
+				self.reader.discard();
+				self.parse_reference(|de| de.deserialize_struct(name, fields, visitor))
+			},
+			Some(_) => Err(Error::SyntaxError),
+			None => Err(self.eof()),
 		}
 	}
 
-	fn parse_string(&mut self) -> Result<String, self::Error> {
-		let mut tab = Vec::new();
-		for _ in 0..2 {
-			let c = try!(self.next_value_or_eof());
-			tab.push(c);
+	/// Null, Undefined and Unsupported are `None`, any other value is `Some`.
+	fn deserialize_option<T: de::Visitor>(self, visitor: T) -> Result<T::Value, self::Error> {
+		match self.peek_marker()? {
+			Some(Marker::Null) | Some(Marker::Undefined) | Some(Marker::Unsupported) => {
+				self.reader.discard();
+				visitor.visit_none()
+			},
+			Some(_) => visitor.visit_some(self),
+			None => Err(self.eof()),
 		}
-		let mut c = io::Cursor::new(tab);
-		let nb = c.read_u16::<BigEndian>().unwrap();
-		let mut str = Vec::new();
-		for _ in 0..nb {
-			match try!(self.reader.next()) {
-				None => return Err(Error::InvalidSize),
-				Some(c) => str.push(c),
-			}
+	}
+
+	/// A char is read from a String holding a single character.
+	fn deserialize_char<T: de::Visitor>(self, visitor: T) -> Result<T::Value, self::Error> {
+		match self.peek_marker()? {
+			Some(Marker::String) => {
+				self.reader.discard();
+				let s = self.parse_string()?;
+				let mut chars = s.chars();
+				match (chars.next(), chars.next()) {
+					(Some(c), None) => visitor.visit_char(c),
+					_ => Err(de::Error::invalid_length(s.chars().count(), &"a single character")),
+				}
+			},
+			_ => self.parse_value(visitor, false),
 		}
-		Ok(String::from_utf8(str).unwrap())
 	}
-}
 
-impl<'a, W> serde::Deserializer for &'a mut StringDeserializer<W>
-	where W: Read,
-{
+	/// Bytes are read from a strict array of Numbers, as they are written.
+	fn deserialize_bytes<T: de::Visitor>(self, visitor: T) -> Result<T::Value, self::Error> {
+		match self.peek_marker()? {
+			Some(Marker::StrictArray) => {
+				self.references.push(self.reader.copy());
+				self.reader.discard();
+				self.nested(|de| {
+					let nb = de.read_u32()?;
+					let mut bytes = Vec::new();
+					for _ in 0..nb {
+						match de.parse_number()? {
+							Some(v) => bytes.push(integer(v, 0., 255.)? as u8),
+							None => return Err(Error::SyntaxError),
+						}
+					}
+					visitor.visit_byte_buf(bytes)
+				})
+			},
+			_ => self.parse_value(visitor, false),
+		}
+	}
 
-	type Error = Error;
+	fn deserialize_byte_buf<T: de::Visitor>(self, visitor: T) -> Result<T::Value, self::Error> {
+		self.deserialize_bytes(visitor)
+	}
 
-	fn deserialize<T: de::Visitor>(self, visitor : T) -> Result<T::Value, self::Error> {
-		let s = try!(self.parse_string());
-		visitor.visit_string(s)
+	deserialize_integer! {
+		deserialize_u8 => visit_u8(u8),
+		deserialize_u16 => visit_u16(u16),
+		deserialize_u32 => visit_u32(u32),
+		deserialize_u64 => visit_u64(u64),
+		deserialize_i8 => visit_i8(i8),
+		deserialize_i16 => visit_i16(i16),
+		deserialize_i32 => visit_i32(i32),
+		deserialize_i64 => visit_i64(i64),
 	}
 
 	forward_to_deserialize! {
-        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char str string
-        unit option seq seq_fixed_size bytes byte_buf map unit_struct
-        newtype_struct tuple_struct struct struct_field tuple enum ignored_any
+        bool f32 f64 str string
+        unit seq seq_fixed_size map unit_struct
+        tuple_struct struct_field tuple ignored_any
 	}	
 }
 
@@ -217,15 +506,15 @@ impl<'a, R: Read + 'a> MapVisitor<'a, R> {
     	match size {
     		None => {
         		MapVisitor {
-            		de: de,
+            		de,
             		size: 0,
             		map: true
         		}    			
     		},
     		Some(size) => {
     			MapVisitor {
-    				de: de,
-    				size: size,
+    				de,
+    				size,
     				map: false
     			}
     		}
@@ -239,11 +528,12 @@ impl<'a, R: Read + 'a> de::MapVisitor for MapVisitor<'a, R> {
     fn visit_key_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, self::Error> 
         where T: de::DeserializeSeed,
    {
-   		let mut de = StringDeserializer{reader: self.de.reader.copy()};
-   		let ret = seed.deserialize(&mut de);
-   		match self.de.parse_string_or_end() {
+   		match self.de.parse_key()? {
 	   		None => if self.map || self.size == 0 { Ok(None) } else { Err(Error::InvalidSize) },
-	   		_ => if self.map || self.size != 0 { if !self.map {self.size -= 1;} Ok(Some(ret.unwrap())) } else { Err(Error::InvalidSize) }
+	   		Some(key) => if self.map || self.size != 0 {
+	   			if !self.map {self.size -= 1;}
+	   			seed.deserialize(ValueDeserializer::<Error>::into_deserializer(key)).map(Some)
+	   		} else { Err(Error::InvalidSize) }
    		}
    }
 
@@ -270,8 +560,8 @@ struct SeqVisitor<'a, R: Read + 'a> {
 impl<'a, R: Read + 'a> SeqVisitor<'a, R> {
     fn new(de: &'a mut Deserializer<R>, size: u32) -> Self {
     	SeqVisitor {
-    		de: de,
-    		size: size,
+    		de,
+    		size,
     	}  			
     }
 }
@@ -283,7 +573,7 @@ impl<'a, R: Read + 'a> de::SeqVisitor for SeqVisitor<'a, R> {
         where T: de::DeserializeSeed,
    {
    		if self.size > 0 {
-	   		let value = try!(seed.deserialize(&mut *self.de));
+	   		let value = seed.deserialize(&mut *self.de)?;
 	   		self.size -= 1;
 	   		Ok(Some(value))
    		} else {
@@ -291,6 +581,225 @@ impl<'a, R: Read + 'a> de::SeqVisitor for SeqVisitor<'a, R> {
    		}
    }
 }
+struct VariantVisitor<'a, R: Read + 'a> {
+    de: &'a mut Deserializer<R>,
+}
+
+impl<'a, R: Read + 'a> VariantVisitor<'a, R> {
+    fn new(de: &'a mut Deserializer<R>) -> Self {
+    	VariantVisitor {
+    		de,
+    	}
+    }
+}
+
+impl<'a, R: Read + 'a> de::EnumVisitor for VariantVisitor<'a, R> {
+    type Error = Error;
+
+    type Variant = Self;
+
+    fn visit_variant_seed<T : de::DeserializeSeed>(self, seed: T) -> Result<(T::Value, Self::Variant), Error>
+    {
+    	let variant = self.de.parse_string()?;
+    	let v = seed.deserialize(ValueDeserializer::<Error>::into_deserializer(variant))?;
+    	Ok((v, self))
+    }
+}
+
+impl<'a, R: Read + 'a> de::VariantVisitor for VariantVisitor<'a, R> {
+    type Error = Error;
+
+    fn visit_unit(self) -> Result<(), Error>
+    {
+    	<() as de::Deserialize>::deserialize(&mut *self.de)?;
+    	self.de.parse_object_end()
+    }
+
+    fn visit_newtype_seed<T : de::DeserializeSeed>(self, seed: T) -> Result<T::Value, Error>
+    {
+    	let value = seed.deserialize(&mut *self.de)?;
+    	self.de.parse_object_end()?;
+    	Ok(value)
+    }
+
+    fn visit_tuple<T: de::Visitor>(self, _len: usize, visitor: T) -> Result<T::Value, Error>
+    {
+    	let value = de::Deserializer::deserialize(&mut *self.de, visitor)?;
+    	self.de.parse_object_end()?;
+    	Ok(value)
+    }
+
+    fn visit_struct<T : de::Visitor>(self, _fields : &'static [&'static str], visitor: T) -> Result<T::Value, Error>
+    {
+    	let value = de::Deserializer::deserialize(&mut *self.de, visitor)?;
+    	self.de.parse_object_end()?;
+    	Ok(value)
+    }
+}
+
+/// Enum access for an AMF type tagged with its name, see `parse_value`.
+struct TaggedVisitor<D> {
+    tag: &'static str,
+    content: D,
+}
+
+impl<D> TaggedVisitor<D> {
+    fn new(tag: &'static str, content: D) -> Self {
+    	TaggedVisitor {
+    		tag,
+    		content,
+    	}
+    }
+}
+
+impl<D: serde::Deserializer<Error = Error>> de::EnumVisitor for TaggedVisitor<D> {
+    type Error = Error;
+
+    type Variant = Self;
+
+    fn visit_variant_seed<T : de::DeserializeSeed>(self, seed: T) -> Result<(T::Value, Self::Variant), Error>
+    {
+    	let v = seed.deserialize(ValueDeserializer::<Error>::into_deserializer(self.tag))?;
+    	Ok((v, self))
+    }
+}
+
+impl<D: serde::Deserializer<Error = Error>> de::VariantVisitor for TaggedVisitor<D> {
+    type Error = Error;
+
+    fn visit_unit(self) -> Result<(), Error>
+    {
+    	Err(de::Error::invalid_type(de::Unexpected::NewtypeVariant, &"unit variant"))
+    }
+
+    fn visit_newtype_seed<T : de::DeserializeSeed>(self, seed: T) -> Result<T::Value, Error>
+    {
+    	seed.deserialize(self.content)
+    }
+
+    fn visit_tuple<T: de::Visitor>(self, _len: usize, visitor: T) -> Result<T::Value, Error>
+    {
+    	self.content.deserialize(visitor)
+    }
+
+    fn visit_struct<T : de::Visitor>(self, _fields : &'static [&'static str], visitor: T) -> Result<T::Value, Error>
+    {
+    	self.content.deserialize(visitor)
+    }
+}
+
+/// Content of a tagged Date: its milliseconds then its time zone.
+struct DateDeserializer {
+    date: Option<f64>,
+    time_zone: Option<i16>,
+}
+
+impl DateDeserializer {
+    fn new(date: f64, time_zone: i16) -> Self {
+    	DateDeserializer {
+    		date: Some(date),
+    		time_zone: Some(time_zone),
+    	}
+    }
+}
+
+impl serde::Deserializer for DateDeserializer {
+	type Error = Error;
+
+	fn deserialize<T: de::Visitor>(self, visitor : T) -> Result<T::Value, self::Error> {
+		visitor.visit_seq(self)
+	}
+
+	forward_to_deserialize! {
+        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char str string
+        unit option seq seq_fixed_size bytes byte_buf map unit_struct
+        newtype_struct tuple_struct struct struct_field tuple enum ignored_any
+	}
+}
+
+impl de::SeqVisitor for DateDeserializer {
+    type Error = Error;
+
+    fn visit_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, self::Error>
+        where T: de::DeserializeSeed,
+   {
+   		if let Some(date) = self.date.take() {
+   			seed.deserialize(ValueDeserializer::<Error>::into_deserializer(date)).map(Some)
+   		} else if let Some(time_zone) = self.time_zone.take() {
+   			seed.deserialize(ValueDeserializer::<Error>::into_deserializer(time_zone)).map(Some)
+   		} else {
+   			Ok(None)
+   		}
+   }
+}
+
+/// Content of a tagged TypedObject: its class name then its properties.
+struct TypedObjectDeserializer<'a, R: Read + 'a> {
+    de: &'a mut Deserializer<R>,
+    class_name: Option<String>,
+    properties: bool,
+}
+
+impl<'a, R: Read + 'a> TypedObjectDeserializer<'a, R> {
+    fn new(de: &'a mut Deserializer<R>, class_name: String) -> Self {
+    	TypedObjectDeserializer {
+    		de,
+    		class_name: Some(class_name),
+    		properties: true,
+    	}
+    }
+}
+
+impl<'a, R: Read + 'a> serde::Deserializer for TypedObjectDeserializer<'a, R> {
+	type Error = Error;
+
+	fn deserialize<T: de::Visitor>(self, visitor : T) -> Result<T::Value, self::Error> {
+		visitor.visit_seq(self)
+	}
+
+	forward_to_deserialize! {
+        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char str string
+        unit option seq seq_fixed_size bytes byte_buf map unit_struct
+        newtype_struct tuple_struct struct struct_field tuple enum ignored_any
+	}
+}
+
+impl<'a, R: Read + 'a> de::SeqVisitor for TypedObjectDeserializer<'a, R> {
+    type Error = Error;
+
+    fn visit_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, self::Error>
+        where T: de::DeserializeSeed,
+   {
+   		if let Some(class_name) = self.class_name.take() {
+   			seed.deserialize(ValueDeserializer::<Error>::into_deserializer(class_name)).map(Some)
+   		} else if self.properties {
+   			self.properties = false;
+   			seed.deserialize(PropertiesDeserializer{de: &mut *self.de}).map(Some)
+   		} else {
+   			Ok(None)
+   		}
+   }
+}
+
+/// Properties of an object whose marker has already been read.
+struct PropertiesDeserializer<'a, R: Read + 'a> {
+    de: &'a mut Deserializer<R>,
+}
+
+impl<'a, R: Read + 'a> serde::Deserializer for PropertiesDeserializer<'a, R> {
+	type Error = Error;
+
+	fn deserialize<T: de::Visitor>(self, visitor : T) -> Result<T::Value, self::Error> {
+		visitor.visit_map(MapVisitor::new(self.de, None))
+	}
+
+	forward_to_deserialize! {
+        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char str string
+        unit option seq seq_fixed_size bytes byte_buf map unit_struct
+        newtype_struct tuple_struct struct struct_field tuple enum ignored_any
+	}
+}
+
 /*
 struct DateVisitor<'a, R: Read + 'a> {
     de: &'a mut Deserializer<R>,
@@ -340,9 +849,63 @@ impl<'a, R: Read + 'a> de::EnumVisitor for DateVisitor<'a, R> {
     }
 }*/
 
+/// Deserialize a value taking the whole slice, trailing bytes are an error.
 pub fn from_slice<T: de::Deserialize>(slice: &[u8]) -> Result<T, Error> {
 	let read = SliceReader::new(slice);
     let mut de = Deserializer::new(read);
-    let value = try!(de::Deserialize::deserialize(&mut de));
+    let value = de::Deserialize::deserialize(&mut de)?;
+    de.end()?;
     Ok(value)
+}
+
+/// Deserialize the value at the start of the slice, ignoring what follows.
+pub fn from_slice_lenient<T: de::Deserialize>(slice: &[u8]) -> Result<T, Error> {
+	let read = SliceReader::new(slice);
+    let mut de = Deserializer::new(read);
+    let value = de::Deserialize::deserialize(&mut de)?;
+    Ok(value)
+}
+
+/// Iterator over consecutive values, such as the arguments of an RTMP command.
+/// Each value comes with the offset of its first byte. Every value has its
+/// own reference table. Iteration stops at the end of the input, or after
+/// the first error.
+pub struct StreamDeserializer<R, T> {
+	de: Deserializer<R>,
+	failed: bool,
+	marker: PhantomData<T>,
+}
+
+impl<R: Read, T: de::Deserialize> Iterator for StreamDeserializer<R, T> {
+	type Item = Result<(usize, T), Error>;
+
+	fn next(&mut self) -> Option<Result<(usize, T), Error>> {
+		if self.failed {
+			return None;
+		}
+		match self.de.reader.peek() {
+			Ok(None) => None,
+			Ok(Some(_)) => {
+				let offset = self.de.reader.offset();
+				self.de.references.clear();
+				self.de.replayed = 0;
+				let value = de::Deserialize::deserialize(&mut self.de);
+				self.failed = value.is_err();
+				Some(value.map(|value| (offset, value)))
+			},
+			Err(err) => {
+				self.failed = true;
+				Some(Err(err))
+			},
+		}
+	}
+}
+
+/// Deserialize a value from a buffered reader, such as a `BufReader`. Only
+/// the bytes of the value are consumed, so pass the reader by reference to
+/// read the next value from it afterwards.
+pub fn from_reader<R: io::BufRead, T: de::Deserialize>(reader: R) -> Result<T, Error> {
+	let mut de = Deserializer::new_from_reader(reader);
+	let value = de::Deserialize::deserialize(&mut de)?;
+	Ok(value)
 }
\ No newline at end of file
@@ -7,15 +7,39 @@ use serde::de;
 #[derive(Debug)]
 pub enum Error {
 	None,
-	UnexpectedEOF,
+	UnexpectedEOF(usize),
+	InvalidUtf8(usize),
 	SyntaxError,
 	InvalidSize,
+	InvalidReference(u16),
+	IntegerOutOfRange(f64),
+	IntegerWithFraction(f64),
+	UnknownMarker { marker: u8, offset: usize },
+	TrailingBytes(usize),
+	DepthLimitExceeded,
+	ReferenceLimitExceeded,
+	UnknownField(String),
+	MissingField(&'static str),
+	DuplicateField(&'static str),
+	Custom(String),
 	IoError(io::Error),
 }
 
 impl de::Error for Error {
-	fn custom<T: fmt::Display>(_msg: T) -> Self {
-		Error::SyntaxError
+	fn custom<T: fmt::Display>(msg: T) -> Self {
+		Error::Custom(msg.to_string())
+	}
+
+	fn unknown_field(field: &str, _expected: &'static [&'static str]) -> Self {
+		Error::UnknownField(field.to_string())
+	}
+
+	fn missing_field(field: &'static str) -> Self {
+		Error::MissingField(field)
+	}
+
+	fn duplicate_field(field: &'static str) -> Self {
+		Error::DuplicateField(field)
 	}
 }
 
@@ -24,14 +48,35 @@ impl error::Error for Error {
 		"Error"
 	}
 
-	fn cause(&self) -> Option<&error::Error> {
-		None
+	fn cause(&self) -> Option<&dyn error::Error> {
+		match *self {
+			Error::IoError(ref err) => Some(err),
+			_ => None,
+		}
 	}
 }
 
 impl fmt::Display for Error {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		write!(f, "Error")
+		match *self {
+			Error::None => write!(f, "unknown error"),
+			Error::UnexpectedEOF(offset) => write!(f, "unexpected end of input at offset {}", offset),
+			Error::InvalidUtf8(offset) => write!(f, "invalid UTF-8 string at offset {}", offset),
+			Error::SyntaxError => write!(f, "syntax error"),
+			Error::InvalidSize => write!(f, "invalid size"),
+			Error::InvalidReference(index) => write!(f, "invalid reference {}", index),
+			Error::IntegerOutOfRange(v) => write!(f, "number {} out of range of the integer type", v),
+			Error::IntegerWithFraction(v) => write!(f, "number {} is not an integer", v),
+			Error::UnknownMarker{marker, offset} => write!(f, "unknown marker 0x{:02X} at offset {}", marker, offset),
+			Error::TrailingBytes(offset) => write!(f, "trailing bytes at offset {}", offset),
+			Error::DepthLimitExceeded => write!(f, "values nested too deeply"),
+			Error::ReferenceLimitExceeded => write!(f, "references expand beyond the limit"),
+			Error::UnknownField(ref field) => write!(f, "unknown field `{}`", field),
+			Error::MissingField(field) => write!(f, "missing field `{}`", field),
+			Error::DuplicateField(field) => write!(f, "duplicate field `{}`", field),
+			Error::Custom(ref msg) => write!(f, "{}", msg),
+			Error::IoError(ref err) => write!(f, "{}", err),
+		}
 	}
 }
 
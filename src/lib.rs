@@ -1,10 +1,11 @@
 #[macro_use]
 extern crate serde;
 
-pub use self::ser::Serializer;
-pub use self::de::{Deserializer, from_slice};
-pub use self::reader::SliceReader;
+pub use self::ser::{IntegerPolicy, Serializer, to_vec, to_writer};
+pub use self::de::{Deserializer, StreamDeserializer, from_reader, from_slice, from_slice_lenient};
+pub use self::reader::{IoReader, SliceReader};
 pub use self::value::Value;
+pub use self::types::{Date, EcmaArray, LongString, TypedObject, Undefined, XmlDocument};
 pub use self::error::Error;
 pub use serde::Deserialize;
 pub use serde::Serialize;
@@ -12,6 +13,7 @@ pub use serde::Serialize;
 pub mod ser;
 pub mod de;
 pub mod value;
+pub mod types;
 pub mod reader;
 pub mod error;
 
@@ -22,12 +24,7 @@ mod tests {
 	#[test]
     fn serialize_true_bool() {
     	let v = Value::Bool(true);
-		let mut ser = Vec::with_capacity(128);
-		{
-			let serializer = self::Serializer {writer: &mut ser };	
- 		   	use serde::Serialize;
-			let _ = v.serialize(serializer);	
-		}
+		let ser = to_vec(&v).unwrap();
 		let true_bool = vec![0x01, 0x01];
     	assert_eq!(ser, true_bool)
     }
@@ -35,12 +32,7 @@ mod tests {
 	#[test]
     fn serialize_false_bool() {
     	let v = Value::Bool(false);
-		let mut ser = Vec::with_capacity(128);
-		{
-			let serializer = self::Serializer {writer: &mut ser };	
- 		   	use serde::Serialize;
-			let _ = v.serialize(serializer);	
-		}
+		let ser = to_vec(&v).unwrap();
 		let false_bool = vec![0x01, 0x00];
     	assert_eq!(ser, false_bool)
     }
@@ -49,7 +41,7 @@ mod tests {
     fn deserialize_true_bool() {
     	let v = [0x01, 0x01];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
 	    let v = Value::deserialize(&mut de).unwrap();
 	    assert_eq!(v, Value::Bool(true))
@@ -59,31 +51,25 @@ mod tests {
     fn deserialize_false_bool() {
     	let v = [0x01, 0x00];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
 	    let v = Value::deserialize(&mut de).unwrap();
 	    assert_eq!(v, Value::Bool(false))
     }
 
 	#[test]
-	#[should_panic]
     fn deserialize_shorter_bool() {
     	let v = [0x01];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
-	    let _ = Value::deserialize(&mut de).unwrap();
+	    assert!(matches!(Value::deserialize(&mut de), Err(Error::UnexpectedEOF(1))))
     }
 
 	#[test]
     fn serialize_zero() {
     	let v = Value::Number(0.);
-		let mut ser = Vec::with_capacity(128);
-		{
-			let serializer = self::Serializer {writer: &mut ser };	
- 		   	use serde::Serialize;
-			let _ = v.serialize(serializer);	
-		}
+		let ser = to_vec(&v).unwrap();
 		let zero = vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
     	assert_eq!(ser, zero)
     }
@@ -91,12 +77,7 @@ mod tests {
 	#[test]
     fn serialize_42() {
     	let v = Value::Number(42.);
-		let mut ser = Vec::with_capacity(128);
-		{
-			let serializer = self::Serializer {writer: &mut ser };	
- 		   	use serde::Serialize;
-			let _ = v.serialize(serializer);	
-		}
+		let ser = to_vec(&v).unwrap();
 		let zero = vec![0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
     	assert_eq!(ser, zero)
     }
@@ -105,7 +86,7 @@ mod tests {
     fn deserialize_zero() {
     	let v = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
 	    let v = Value::deserialize(&mut de).unwrap();
 	    assert_eq!(v, Value::Number(0.))
@@ -115,31 +96,25 @@ mod tests {
     fn deserialize_42() {
     	let v = [0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
 	    let v = Value::deserialize(&mut de).unwrap();
 	    assert_eq!(v, Value::Number(42.))
     }
 
 	#[test]
-	#[should_panic]
     fn deserialize_shorter_number() {
     	let v = [0x00, 0x00, 0x00];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
-	    let _ = Value::deserialize(&mut de).unwrap();
+	    assert!(matches!(Value::deserialize(&mut de), Err(Error::UnexpectedEOF(3))))
     }
 
 	#[test]
     fn serialize_string_empty() {
     	let v = Value::String("".to_string());
-		let mut ser = Vec::with_capacity(128);
-		{
-			let serializer = self::Serializer {writer: &mut ser };	
- 		   	use serde::Serialize;
-			let _ = v.serialize(serializer);	
-		}
+		let ser = to_vec(&v).unwrap();
 		let empty = vec![0x02, 0x00, 0x00];
     	assert_eq!(ser, empty)
     }
@@ -147,12 +122,7 @@ mod tests {
 	#[test]
     fn serialize_string_hello() {
     	let v = Value::String("hello".to_string());
-		let mut ser = Vec::with_capacity(128);
-		{
-			let serializer = self::Serializer {writer: &mut ser };	
- 		   	use serde::Serialize;
-			let _ = v.serialize(serializer);	
-		}
+		let ser = to_vec(&v).unwrap();
 		let empty = vec![0x02, 0x00, 0x05, 0x68, 0x65, 0x6C, 0x6C, 0x6F];
     	assert_eq!(ser, empty)
     }
@@ -161,7 +131,7 @@ mod tests {
     fn deserialize_string_empty() {
     	let v = [0x02, 0x00, 0x00];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
 	    let v = Value::deserialize(&mut de).unwrap();
 	    assert_eq!(v, Value::String("".to_string()))
@@ -172,7 +142,7 @@ mod tests {
     fn deserialize_string_hello() {
     	let v = [0x02, 0x00, 0x05, 0x68, 0x65, 0x6C, 0x6C, 0x6F];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
 	    let v = Value::deserialize(&mut de).unwrap();
 	    assert_eq!(v, Value::String("hello".to_string()))
@@ -180,35 +150,28 @@ mod tests {
     }
 
 	#[test]
-	#[should_panic]
     fn deserialize_shorter_string() {
     	let v = [0x02, 0x00];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
-	    let _ = Value::deserialize(&mut de).unwrap();
+	    assert!(matches!(Value::deserialize(&mut de), Err(Error::UnexpectedEOF(2))))
     }
 
 	#[test]
-	#[should_panic]
     fn deserialize_string_size_too_long() {
     	let v = [0x02, 0x00, 0x03, 0x00];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
-	    let _ = Value::deserialize(&mut de).unwrap();
+	    assert!(matches!(Value::deserialize(&mut de), Err(Error::UnexpectedEOF(4))))
     }
 
 	#[test]
     fn serialize_object_empty() {
     	let map = value::Map::new();
     	let v = Value::Object(map);
-		let mut ser = Vec::with_capacity(128);
-		{
-			let serializer = self::Serializer {writer: &mut ser };	
- 		   	use serde::Serialize;
-			let _ = v.serialize(serializer);	
-		}
+		let ser = to_vec(&v).unwrap();
 		let object = vec![0x03, 0x00, 0x00, 0x09];
     	assert_eq!(ser, object)
     }
@@ -220,12 +183,7 @@ mod tests {
 		map.insert("2".to_string(), Value::String("hello".to_string()));
 		map.insert("3".to_string(), Value::Bool(true));
     	let v = Value::Object(map);
-		let mut ser = Vec::with_capacity(128);
-		{
-			let serializer = self::Serializer {writer: &mut ser };	
- 		   	use serde::Serialize;
-			let _ = v.serialize(serializer);	
-		}
+		let ser = to_vec(&v).unwrap();
 		let object = vec![0x03,
 		 0x00, 0x01, 0x31, 0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 		 0x00, 0x01, 0x32, 0x02, 0x00, 0x05, 0x68, 0x65, 0x6C, 0x6C, 0x6F,
@@ -238,7 +196,7 @@ mod tests {
     fn deserialize_object_empty() {
     	let v = [0x03, 0x00, 0x00, 0x09];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
 	    let v = Value::deserialize(&mut de).unwrap();
     	let map = value::Map::new();
@@ -253,7 +211,7 @@ mod tests {
 		 0x00, 0x01, 0x33, 0x01, 0x01,
 		 0x00, 0x00, 0x09];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
 	    let v = Value::deserialize(&mut de).unwrap();
     	let mut map = value::Map::new();
@@ -264,25 +222,19 @@ mod tests {
     }
 
 	#[test]
-	#[should_panic]
     fn deserialize_shorter_object() {
     	let v = [0x03];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
-	    let _ = Value::deserialize(&mut de).unwrap();
+	    assert!(matches!(Value::deserialize(&mut de), Err(Error::UnexpectedEOF(1))))
     }
 
 	#[test]
 	fn serialize_array_empty() {
     	let map = value::Map::new();
     	let v = Value::ECMAArray(map);
-		let mut ser = Vec::with_capacity(128);
-		{
-			let serializer = self::Serializer {writer: &mut ser };	
- 		   	use serde::Serialize;
-			let _ = v.serialize(serializer);	
-		}
+		let ser = to_vec(&v).unwrap();
 		let tab = vec![0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x09];
     	assert_eq!(ser, tab)
 	}
@@ -293,12 +245,7 @@ mod tests {
 		map.insert("1".to_string(), Value::Number(42.));
 		map.insert("2".to_string(), Value::Number(-42.));
     	let v = Value::ECMAArray(map);
-		let mut ser = Vec::with_capacity(128);
-		{
-			let serializer = self::Serializer {writer: &mut ser };	
- 		   	use serde::Serialize;
-			let _ = v.serialize(serializer);	
-		}
+		let ser = to_vec(&v).unwrap();
 		let tab = vec![0x08, 0x00, 0x00, 0x00, 0x02,
 		0x00, 0x01, 0x31, 0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 		0x00, 0x01, 0x32, 0x00, 0xC0, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -310,7 +257,7 @@ mod tests {
 	fn deserialize_array_empty() {
 		let v = vec![0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x09];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
 	    let v = Value::deserialize(&mut de).unwrap();
     	let map = value::Map::new();
@@ -324,7 +271,7 @@ mod tests {
 		0x00, 0x01, 0x32, 0x00, 0xC0, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 		0x00, 0x00, 0x09];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
 	    let v = Value::deserialize(&mut de).unwrap();
     	let mut map = value::Map::new();
@@ -334,46 +281,38 @@ mod tests {
 	}
 
 	#[test]
-	#[should_panic]
     fn deserialize_shorter_array() {
     	let v = [0x08, 0x00];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
-	    let _ = Value::deserialize(&mut de).unwrap();
+	    assert!(matches!(Value::deserialize(&mut de), Err(Error::UnexpectedEOF(2))))
     }
 
 	#[test]
-	#[should_panic]
     fn deserialize_array_size_too_long() {
     	let v = [0x08, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x09];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
-	    let _ = Value::deserialize(&mut de).unwrap();
+	    assert!(matches!(Value::deserialize(&mut de), Err(Error::InvalidSize)))
     }
 
 	#[test]
-	#[should_panic]
     fn deserialize_array_size_too_short() {
     	let v = [0x08, 0x00, 0x00, 0x00, 0x00,
 		0x00, 0x01, 0x31, 0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 		0x00, 0x00, 0x09];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
-	    let _ = Value::deserialize(&mut de).unwrap();
+	    assert!(matches!(Value::deserialize(&mut de), Err(Error::InvalidSize)))
     }
 
 	#[test]
 	fn serialize_unit() {
     	let v = Value::Null;
-		let mut ser = Vec::with_capacity(128);
-		{
-			let serializer = self::Serializer {writer: &mut ser };	
- 		   	use serde::Serialize;
-			let _ = v.serialize(serializer);	
-		}
+		let ser = to_vec(&v).unwrap();
 		let empty = vec![0x05];
     	assert_eq!(ser, empty)
 	}
@@ -382,7 +321,7 @@ mod tests {
 	fn deserialize_unit() {
 		let v = vec![0x05];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
 	    let v = Value::deserialize(&mut de).unwrap();
 	    assert_eq!(v, Value::Null)		
@@ -392,7 +331,7 @@ mod tests {
 	fn deserialize_undefined() {
 		let v = vec![0x06];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
 	    let v = Value::deserialize(&mut de).unwrap();
 	    assert_eq!(v, Value::Undefined)		
@@ -402,7 +341,7 @@ mod tests {
 	fn deserialize_empty_strict_array() {
 		let v = vec![0x0A, 0x00, 0x00, 0x00, 0x00];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
 	    let v = Value::deserialize(&mut de).unwrap();
 	    let vec = vec![];
@@ -417,7 +356,7 @@ mod tests {
 		0x00, 0xC0, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
 		];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
 	    let v = Value::deserialize(&mut de).unwrap();
 	    let mut vec = vec![];
@@ -437,7 +376,7 @@ mod tests {
 		0x00, 0xC0, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
 		];
 	    let s = SliceReader::new(&v);
-	    let mut de = Deserializer{reader: s};
+	    let mut de = Deserializer::new(s);
 	    use serde::Deserialize;
 	    let v = Value::deserialize(&mut de).unwrap();
 	    let mut vec = vec![];
@@ -449,4 +388,1627 @@ mod tests {
 	    assert_eq!(v, Value::StrictArray(vec))
 	}
 
+	struct Connect {
+		app: String,
+		version: f64,
+		secure: bool,
+	}
+
+	impl Serialize for Connect {
+		fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+			use serde::ser::SerializeStruct;
+			let mut s = serializer.serialize_struct("Connect", 3)?;
+			s.serialize_field("app", &self.app)?;
+			s.serialize_field("version", &self.version)?;
+			s.serialize_field("secure", &self.secure)?;
+			s.end()
+		}
+	}
+
+	#[test]
+	fn serialize_struct() {
+		let v = Connect{app: "live".to_string(), version: 42., secure: true};
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		let object = vec![0x03,
+		 0x00, 0x03, 0x61, 0x70, 0x70, 0x02, 0x00, 0x04, 0x6C, 0x69, 0x76, 0x65,
+		 0x00, 0x07, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6F, 0x6E, 0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+		 0x00, 0x06, 0x73, 0x65, 0x63, 0x75, 0x72, 0x65, 0x01, 0x01,
+		 0x00, 0x00, 0x09];
+		assert_eq!(ser, object)
+	}
+
+	#[test]
+	fn serialize_struct_as_value() {
+		let v = Connect{app: "live".to_string(), version: 42., secure: true};
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		let v = from_slice::<Value>(&ser).unwrap();
+		let mut map = value::Map::new();
+		map.insert("app".to_string(), Value::String("live".to_string()));
+		map.insert("version".to_string(), Value::Number(42.));
+		map.insert("secure".to_string(), Value::Bool(true));
+		assert_eq!(v, Value::Object(map))
+	}
+
+	#[test]
+	fn serialize_empty_strict_array() {
+		let v = Value::StrictArray(vec![]);
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		let tab = vec![0x0A, 0x00, 0x00, 0x00, 0x00];
+		assert_eq!(ser, tab)
+	}
+
+	#[test]
+	fn serialize_strict_array() {
+		let v = vec![42., -42.];
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		let tab = vec![
+		0x0A, 0x00, 0x00, 0x00, 0x02,
+		0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+		0x00, 0xC0, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
+		];
+		assert_eq!(ser, tab)
+	}
+
+	struct Unsized(Vec<f64>);
+
+	impl Serialize for Unsized {
+		fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+			use serde::ser::SerializeSeq;
+			let mut s = serializer.serialize_seq(None)?;
+			for e in &self.0 {
+				s.serialize_element(e)?;
+			}
+			s.end()
+		}
+	}
+
+	#[test]
+	fn serialize_strict_array_unknown_length() {
+		let v = Unsized(vec![42., -42.]);
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		let tab = vec![
+		0x0A, 0x00, 0x00, 0x00, 0x02,
+		0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+		0x00, 0xC0, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
+		];
+		assert_eq!(ser, tab)
+	}
+
+	#[test]
+	fn strict_array_round_trip() {
+		let v = Value::StrictArray(vec![Value::Undefined, Value::Number(42.), Value::String("hello".to_string())]);
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		assert_eq!(from_slice::<Value>(&ser).unwrap(), v)
+	}
+
+	struct Command(String, f64, ());
+
+	impl Serialize for Command {
+		fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+			use serde::ser::SerializeTupleStruct;
+			let mut s = serializer.serialize_tuple_struct("Command", 3)?;
+			s.serialize_field(&self.0)?;
+			s.serialize_field(&self.1)?;
+			s.serialize_field(&self.2)?;
+			s.end()
+		}
+	}
+
+	#[test]
+	fn serialize_tuple() {
+		let v = ("hello".to_string(), 42.);
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		let tab = vec![0x0A, 0x00, 0x00, 0x00, 0x02,
+		0x02, 0x00, 0x05, 0x68, 0x65, 0x6C, 0x6C, 0x6F,
+		0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+		assert_eq!(ser, tab);
+		assert_eq!(from_slice::<(String, f64)>(&ser).unwrap(), v)
+	}
+
+	#[test]
+	fn serialize_tuple_struct() {
+		let v = Command("connect".to_string(), 1., ());
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		let tab = vec![0x0A, 0x00, 0x00, 0x00, 0x03,
+		0x02, 0x00, 0x07, 0x63, 0x6F, 0x6E, 0x6E, 0x65, 0x63, 0x74,
+		0x00, 0x3F, 0xF0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+		0x05];
+		assert_eq!(ser, tab)
+	}
+
+	#[test]
+	fn serialize_flattened_tuple_struct() {
+		let v = Command("connect".to_string(), 1., ());
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser).flatten(true);
+			v.serialize(serializer).unwrap();
+		}
+		let tab = vec![
+		0x02, 0x00, 0x07, 0x63, 0x6F, 0x6E, 0x6E, 0x65, 0x63, 0x74,
+		0x00, 0x3F, 0xF0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+		0x05];
+		assert_eq!(ser, tab)
+	}
+
+	#[test]
+	fn serialize_flattened_nested_tuple() {
+		let v = (true, (true,));
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser).flatten(true);
+			v.serialize(serializer).unwrap();
+		}
+		let tab = vec![0x01, 0x01, 0x0A, 0x00, 0x00, 0x00, 0x01, 0x01, 0x01];
+		assert_eq!(ser, tab)
+	}
+
+	#[derive(Debug, PartialEq)]
+	enum Status {
+		Idle,
+		Code(f64),
+		Pair(f64, bool),
+		Info { level: String },
+	}
+
+	impl Serialize for Status {
+		fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+			use serde::ser::{SerializeTupleVariant, SerializeStructVariant};
+			match *self {
+				Status::Idle => serializer.serialize_unit_variant("Status", 0, "Idle"),
+				Status::Code(ref v) => serializer.serialize_newtype_variant("Status", 1, "Code", v),
+				Status::Pair(ref a, ref b) => {
+					let mut s = serializer.serialize_tuple_variant("Status", 2, "Pair", 2)?;
+					s.serialize_field(a)?;
+					s.serialize_field(b)?;
+					s.end()
+				},
+				Status::Info { ref level } => {
+					let mut s = serializer.serialize_struct_variant("Status", 3, "Info", 1)?;
+					s.serialize_field("level", level)?;
+					s.end()
+				},
+			}
+		}
+	}
+
+	impl Deserialize for Status {
+		fn deserialize<D: serde::Deserializer>(deserializer: D) -> Result<Status, D::Error> {
+			use std::fmt;
+			use serde::de::{self, EnumVisitor, VariantVisitor};
+
+			struct PairVisitor;
+
+			impl de::Visitor for PairVisitor {
+				type Value = Status;
+
+				fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+					f.write_str("Pair")
+				}
+
+				fn visit_seq<V: de::SeqVisitor>(self, mut visitor: V) -> Result<Status, V::Error> {
+					let a = visitor.visit()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+					let b = visitor.visit()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+					Ok(Status::Pair(a, b))
+				}
+			}
+
+			struct InfoVisitor;
+
+			impl de::Visitor for InfoVisitor {
+				type Value = Status;
+
+				fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+					f.write_str("Info")
+				}
+
+				fn visit_map<V: de::MapVisitor>(self, mut visitor: V) -> Result<Status, V::Error> {
+					let mut level = None;
+					while let Some((key, value)) = visitor.visit::<String, String>()? {
+						if key == "level" {
+							level = Some(value);
+						}
+					}
+					Ok(Status::Info { level: level.ok_or_else(|| de::Error::missing_field("level"))? })
+				}
+			}
+
+			struct StatusVisitor;
+
+			impl de::Visitor for StatusVisitor {
+				type Value = Status;
+
+				fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+					f.write_str("Status")
+				}
+
+				fn visit_enum<V: EnumVisitor>(self, visitor: V) -> Result<Status, V::Error> {
+					let (variant, content) = visitor.visit_variant::<String>()?;
+					match variant.as_str() {
+						"Idle" => content.visit_unit().map(|_| Status::Idle),
+						"Code" => content.visit_newtype().map(Status::Code),
+						"Pair" => content.visit_tuple(2, PairVisitor),
+						"Info" => content.visit_struct(&["level"], InfoVisitor),
+						v => Err(de::Error::unknown_variant(v, &["Idle", "Code", "Pair", "Info"])),
+					}
+				}
+			}
+
+			deserializer.deserialize_enum("Status", &["Idle", "Code", "Pair", "Info"], StatusVisitor)
+		}
+	}
+
+	#[test]
+	fn serialize_unit_variant() {
+		let v = Status::Idle;
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		let tab = vec![0x02, 0x00, 0x04, 0x49, 0x64, 0x6C, 0x65];
+		assert_eq!(ser, tab);
+		assert_eq!(from_slice::<Status>(&ser).unwrap(), v)
+	}
+
+	#[test]
+	fn serialize_newtype_variant() {
+		let v = Status::Code(42.);
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		let tab = vec![0x03,
+		0x00, 0x04, 0x43, 0x6F, 0x64, 0x65, 0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+		0x00, 0x00, 0x09];
+		assert_eq!(ser, tab);
+		assert_eq!(from_slice::<Status>(&ser).unwrap(), v)
+	}
+
+	#[test]
+	fn serialize_tuple_variant() {
+		let v = Status::Pair(42., true);
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		let tab = vec![0x03,
+		0x00, 0x04, 0x50, 0x61, 0x69, 0x72, 0x0A, 0x00, 0x00, 0x00, 0x02,
+		0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+		0x01, 0x01,
+		0x00, 0x00, 0x09];
+		assert_eq!(ser, tab);
+		assert_eq!(from_slice::<Status>(&ser).unwrap(), v)
+	}
+
+	#[test]
+	fn serialize_struct_variant() {
+		let v = Status::Info { level: "error".to_string() };
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		let tab = vec![0x03,
+		0x00, 0x04, 0x49, 0x6E, 0x66, 0x6F, 0x03,
+		0x00, 0x05, 0x6C, 0x65, 0x76, 0x65, 0x6C, 0x02, 0x00, 0x05, 0x65, 0x72, 0x72, 0x6F, 0x72,
+		0x00, 0x00, 0x09,
+		0x00, 0x00, 0x09];
+		assert_eq!(ser, tab);
+		assert_eq!(from_slice::<Status>(&ser).unwrap(), v)
+	}
+
+	#[test]
+	fn deserialize_variant_missing_end() {
+		let v = vec![0x03,
+		0x00, 0x04, 0x43, 0x6F, 0x64, 0x65, 0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+		0x00, 0x01, 0x31, 0x05];
+		assert!(from_slice::<Status>(&v).is_err())
+	}
+
+	#[derive(Debug, PartialEq)]
+	struct AppName(String);
+
+	impl Serialize for AppName {
+		fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+			serializer.serialize_newtype_struct("AppName", &self.0)
+		}
+	}
+
+	impl Deserialize for AppName {
+		fn deserialize<D: serde::Deserializer>(deserializer: D) -> Result<AppName, D::Error> {
+			use std::fmt;
+
+			struct AppNameVisitor;
+
+			impl serde::de::Visitor for AppNameVisitor {
+				type Value = AppName;
+
+				fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+					f.write_str("AppName")
+				}
+
+				fn visit_newtype_struct<D: serde::Deserializer>(self, deserializer: D) -> Result<AppName, D::Error> {
+					String::deserialize(deserializer).map(AppName)
+				}
+			}
+
+			deserializer.deserialize_newtype_struct("AppName", AppNameVisitor)
+		}
+	}
+
+	#[test]
+	fn serialize_newtype_struct() {
+		let v = AppName("live".to_string());
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		let tab = vec![0x02, 0x00, 0x04, 0x6C, 0x69, 0x76, 0x65];
+		assert_eq!(ser, tab);
+		assert_eq!(from_slice::<AppName>(&ser).unwrap(), v)
+	}
+
+	#[test]
+	fn serialize_string_max_size() {
+		let v = "a".repeat(65535);
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		assert_eq!(ser[..3], [0x02, 0xFF, 0xFF]);
+		assert_eq!(ser.len(), 3 + 65535)
+	}
+
+	#[test]
+	fn serialize_string_too_long_as_long_string() {
+		let v = "a".repeat(65536);
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		assert_eq!(ser[..5], [0x0C, 0x00, 0x01, 0x00, 0x00]);
+		assert_eq!(ser.len(), 5 + 65536)
+	}
+
+	#[test]
+	fn serialize_long_string() {
+		let v = Value::LongString("hello".to_string());
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		let tab = vec![0x0C, 0x00, 0x00, 0x00, 0x05, 0x68, 0x65, 0x6C, 0x6C, 0x6F];
+		assert_eq!(ser, tab)
+	}
+
+	#[test]
+	fn serialize_key_too_long() {
+		let mut map = std::collections::BTreeMap::new();
+		map.insert("a".repeat(65536), 42.);
+		let mut ser = Vec::with_capacity(128);
+		let serializer = self::Serializer::new(&mut ser);
+		assert!(map.serialize(serializer).is_err())
+	}
+
+	#[test]
+	fn serialize_error_display() {
+		struct Failing;
+
+		impl Serialize for Failing {
+			fn serialize<S: serde::Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+				Err(serde::ser::Error::custom("no AMF form"))
+			}
+		}
+
+		match to_vec(&vec![Failing]) {
+			Err(ser::Error::Custom(ref msg)) => assert_eq!(msg, "no AMF form"),
+			v => panic!("{:?}", v),
+		}
+		let key = "a".repeat(65536);
+		let err = to_vec(&Value::Object(std::iter::once((key, Value::Null)).collect())).unwrap_err();
+		assert_eq!(err.to_string(), "string or key too long for its length field")
+	}
+
+	#[test]
+	fn deserialize_long_string() {
+		let v = [0x0C, 0x00, 0x00, 0x00, 0x05, 0x68, 0x65, 0x6C, 0x6C, 0x6F];
+		assert_eq!(from_slice::<Value>(&v).unwrap(), Value::LongString("hello".to_string()));
+		assert_eq!(from_slice::<String>(&v).unwrap(), "hello".to_string());
+		assert_eq!(Value::LongString("hello".to_string()).to_string(), "hello")
+	}
+
+	#[test]
+	fn deserialize_long_string_in_strict_array() {
+		let v = vec![0x0A, 0x00, 0x00, 0x00, 0x02,
+		0x0C, 0x00, 0x00, 0x00, 0x05, 0x68, 0x65, 0x6C, 0x6C, 0x6F,
+		0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+		let v = from_slice::<Value>(&v).unwrap();
+		assert_eq!(v, Value::StrictArray(vec![Value::LongString("hello".to_string()), Value::Number(42.)]))
+	}
+
+	#[test]
+	fn deserialize_shorter_long_string() {
+		let v = [0x0C, 0x00, 0x00, 0x00, 0x05, 0x68];
+		assert!(matches!(from_slice::<Value>(&v), Err(Error::UnexpectedEOF(6))))
+	}
+
+	#[test]
+	fn long_string_round_trip() {
+		let v = Value::LongString("a".repeat(70000));
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		assert_eq!(from_slice::<Value>(&ser).unwrap(), v)
+	}
+
+	#[test]
+	fn serialize_date() {
+		let v = Value::Date(value::Date::with_time_zone(1488371400250., 60));
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser);
+			v.serialize(serializer).unwrap();
+		}
+		let date = vec![0x0B, 0x42, 0x75, 0xA8, 0x9D, 0x93, 0xE3, 0xA0, 0x00, 0x00, 0x3C];
+		assert_eq!(ser, date)
+	}
+
+	#[test]
+	fn deserialize_date() {
+		let v = [0x0B, 0x42, 0x75, 0xA8, 0x9D, 0x93, 0xE3, 0xA0, 0x00, 0x00, 0x3C];
+		let date = value::Date::with_time_zone(1488371400250., 60);
+		assert_eq!(from_slice::<Value>(&v).unwrap(), Value::Date(date));
+		assert_eq!(from_slice::<value::Date>(&v).unwrap(), date);
+		assert_eq!(from_slice::<f64>(&v).unwrap(), 1488371400250.)
+	}
+
+	#[test]
+	fn deserialize_date_in_strict_array() {
+		let v = vec![0x0A, 0x00, 0x00, 0x00, 0x02,
+		0x0B, 0x42, 0x75, 0xA8, 0x9D, 0x93, 0xE3, 0xA0, 0x00, 0x00, 0x00,
+		0x01, 0x01];
+		let v = from_slice::<Value>(&v).unwrap();
+		assert_eq!(v, Value::StrictArray(vec![Value::Date(value::Date::from_millis(1488371400250.)), Value::Bool(true)]))
+	}
+
+	#[test]
+	fn deserialize_shorter_date() {
+		let v = [0x0B, 0x42, 0x75, 0xA8, 0x9D, 0x93, 0xE3, 0xA0, 0x00, 0x00];
+		assert!(matches!(from_slice::<Value>(&v), Err(Error::UnexpectedEOF(10))))
+	}
+
+	#[test]
+	fn date_iso_8601() {
+		let date = value::Date::from_millis(1488371400250.);
+		assert_eq!(date.to_string(), "2017-03-01T12:30:00.250Z");
+		assert_eq!("2017-03-01T12:30:00.250Z".parse::<value::Date>().unwrap(), date);
+		let date = value::Date::with_time_zone(1488371400250., 60);
+		assert_eq!(date.to_string(), "2017-03-01T13:30:00.250+01:00");
+		assert_eq!("2017-03-01T13:30:00.25+01:00".parse::<value::Date>().unwrap(), date);
+		assert_eq!(value::Date::from_millis(-1.).to_string(), "1969-12-31T23:59:59.999Z");
+		assert_eq!("2017-03-01T12:30:00".parse::<value::Date>().unwrap().millis(), 1488371400000.);
+		assert!("2017-13-01T12:30:00Z".parse::<value::Date>().is_err());
+		assert!("2017-03-01 garbage".parse::<value::Date>().is_err());
+		assert!("2017-03-01T12:30:00+1".parse::<value::Date>().is_err())
+	}
+
+	#[test]
+	fn date_out_of_range() {
+		assert_eq!(value::Date::with_time_zone(1e300, 60).to_string(), "Invalid Date");
+		assert_eq!(value::Date::from_millis(-8.64e15 - 1.).to_string(), "Invalid Date");
+		assert_eq!(value::Date::from_millis(8.64e15).to_string(), "+275760-09-13T00:00:00.000Z");
+		assert!(value::Date::with_time_zone(0., i16::MIN).to_string().ends_with("-546:08"));
+		let v = [0x0B, 0x7F, 0xE0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x00];
+		assert_eq!(from_slice::<Value>(&v).unwrap().to_string(), "Invalid Date")
+	}
+
+	#[test]
+	fn date_system_time() {
+		use std::time::{Duration, UNIX_EPOCH};
+		let time = UNIX_EPOCH + Duration::from_millis(1488371400250);
+		let date = value::Date::from(time);
+		assert_eq!(date.millis(), 1488371400250.);
+		assert_eq!(date.time_zone(), 0);
+		assert_eq!(date.to_system_time(), Some(time));
+		assert_eq!(value::Date::from_millis(f64::NAN).to_system_time(), None)
+	}
+
+	fn to_bytes<T: Serialize>(v: &T) -> Vec<u8> {
+		to_vec(v).unwrap()
+	}
+
+	#[test]
+	fn serialize_xml_document_wrapper() {
+		let v = XmlDocument("<a/>".to_string());
+		let ser = to_bytes(&v);
+		assert_eq!(ser, vec![0x0F, 0x00, 0x00, 0x00, 0x04, 0x3C, 0x61, 0x2F, 0x3E]);
+		assert_eq!(from_slice::<XmlDocument>(&ser).unwrap(), v);
+		assert_eq!(from_slice::<String>(&ser).unwrap(), "<a/>".to_string())
+	}
+
+	#[test]
+	fn serialize_long_string_wrapper() {
+		let v = LongString("hello".to_string());
+		let ser = to_bytes(&v);
+		assert_eq!(ser, vec![0x0C, 0x00, 0x00, 0x00, 0x05, 0x68, 0x65, 0x6C, 0x6C, 0x6F]);
+		assert_eq!(from_slice::<LongString>(&ser).unwrap(), v);
+		assert_eq!(from_slice::<String>(&ser).unwrap(), "hello".to_string());
+		assert_eq!(from_slice::<LongString>(&to_bytes(&"hello")).unwrap(), v);
+		assert!(from_slice::<LongString>(&to_bytes(&XmlDocument("hello".to_string()))).is_err())
+	}
+
+	#[test]
+	fn serialize_undefined_wrapper() {
+		let ser = to_bytes(&Undefined);
+		assert_eq!(ser, vec![0x06]);
+		assert_eq!(from_slice::<Undefined>(&ser).unwrap(), Undefined)
+	}
+
+	#[test]
+	fn serialize_date_wrapper() {
+		let v = Date::from_millis(0.);
+		let ser = to_bytes(&v);
+		assert_eq!(ser, vec![0x0B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+		assert_eq!(from_slice::<Date>(&ser).unwrap(), v)
+	}
+
+	#[test]
+	fn serialize_ecma_array_wrapper() {
+		let v = EcmaArray(Connect{app: "live".to_string(), version: 42., secure: true});
+		let ser = to_bytes(&v);
+		let tab = vec![0x08, 0x00, 0x00, 0x00, 0x03,
+		 0x00, 0x03, 0x61, 0x70, 0x70, 0x02, 0x00, 0x04, 0x6C, 0x69, 0x76, 0x65,
+		 0x00, 0x07, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6F, 0x6E, 0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+		 0x00, 0x06, 0x73, 0x65, 0x63, 0x75, 0x72, 0x65, 0x01, 0x01,
+		 0x00, 0x00, 0x09];
+		assert_eq!(ser, tab);
+		let v = from_slice::<EcmaArray<value::Map<String, Value>>>(&ser).unwrap();
+		assert_eq!(v.0.get("app"), Some(&Value::String("live".to_string())))
+	}
+
+	#[test]
+	fn serialize_ecma_array_wrapper_unknown_length() {
+		let mut map = value::Map::new();
+		map.insert("1".to_string(), Value::Number(42.));
+		let v = EcmaArray(Value::Object(map.clone()));
+		let ser = to_bytes(&v);
+		let tab = vec![0x08, 0x00, 0x00, 0x00, 0x01,
+		0x00, 0x01, 0x31, 0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+		0x00, 0x00, 0x09];
+		assert_eq!(ser, tab);
+		assert_eq!(from_slice::<Value>(&ser).unwrap(), Value::ECMAArray(map))
+	}
+
+	#[test]
+	fn serialize_typed_object_wrapper() {
+		let mut map = value::Map::new();
+		map.insert("1".to_string(), Value::Number(42.));
+		let v = TypedObject::new("Point", map);
+		let ser = to_bytes(&v);
+		let tab = vec![0x10,
+		0x00, 0x05, 0x50, 0x6F, 0x69, 0x6E, 0x74,
+		0x00, 0x01, 0x31, 0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+		0x00, 0x00, 0x09];
+		assert_eq!(ser, tab);
+		assert_eq!(from_slice::<TypedObject<value::Map<String, Value>>>(&ser).unwrap(), v)
+	}
+
+	#[test]
+	fn deserialize_typed_object_wrapper_from_object() {
+		let v = [0x03, 0x00, 0x01, 0x31, 0x01, 0x01, 0x00, 0x00, 0x09];
+		let v = from_slice::<TypedObject<value::Map<String, bool>>>(&v).unwrap();
+		assert_eq!(v.class_name, "");
+		assert_eq!(v.value.get("1"), Some(&true))
+	}
+
+	#[test]
+	fn serialize_ecma_array_wrapper_of_non_object() {
+		assert!(matches!(to_vec(&EcmaArray(1u8)), Err(ser::Error::InvalidEcmaArray)));
+		assert!(matches!(to_vec(&EcmaArray(vec![1u8])), Err(ser::Error::InvalidEcmaArray)));
+		assert!(matches!(to_vec(&EcmaArray(Value::Number(1.))), Err(ser::Error::InvalidEcmaArray)));
+		let mut map = value::Map::new();
+		map.insert("1".to_string(), Value::Number(42.));
+		assert_eq!(from_slice::<Value>(&to_bytes(&EcmaArray(Value::Object(map.clone())))).unwrap(), Value::ECMAArray(map))
+	}
+
+	#[test]
+	fn serialize_typed_object_wrapper_of_non_object() {
+		assert!(matches!(to_vec(&TypedObject::new("Point", 42.)), Err(ser::Error::InvalidTypedObject)));
+		assert!(matches!(to_vec(&TypedObject::new("Point", "x")), Err(ser::Error::InvalidTypedObject)));
+		assert!(matches!(to_vec(&TypedObject::new("Point", vec![1., 2.])), Err(ser::Error::InvalidTypedObject)));
+		let err = to_vec(&TypedObject::new("Point", EcmaArray(value::Map::<String, f64>::new()))).unwrap_err();
+		assert_eq!(err.to_string(), "a TypedObject must hold a struct or a map");
+		let v = TypedObject::new("Connect", Some(Connect{app: "live".to_string(), version: 42., secure: true}));
+		let v = from_slice::<Value>(&to_bytes(&v)).unwrap();
+		assert!(matches!(v, Value::TypedObject(ref name, _) if name == "Connect"))
+	}
+
+	#[test]
+	fn serialize_xml_document() {
+		let v = Value::XMLDocument("<a/>".to_string());
+		let ser = to_bytes(&v);
+		assert_eq!(ser, vec![0x0F, 0x00, 0x00, 0x00, 0x04, 0x3C, 0x61, 0x2F, 0x3E])
+	}
+
+	#[test]
+	fn deserialize_xml_document() {
+		let v = [0x0F, 0x00, 0x00, 0x00, 0x04, 0x3C, 0x61, 0x2F, 0x3E];
+		assert_eq!(from_slice::<Value>(&v).unwrap(), Value::XMLDocument("<a/>".to_string()));
+		assert_eq!(from_slice::<String>(&v).unwrap(), "<a/>".to_string())
+	}
+
+	#[test]
+	fn xml_document_round_trip() {
+		let v = Value::StrictArray(vec![Value::XMLDocument("<a>b</a>".to_string()), Value::String("<a>b</a>".to_string())]);
+		let ser = to_bytes(&v);
+		assert_eq!(from_slice::<Value>(&ser).unwrap(), v)
+	}
+
+	#[test]
+	fn serialize_typed_object() {
+		let mut map = value::Map::new();
+		map.insert("1".to_string(), Value::Number(42.));
+		let v = Value::TypedObject("Point".to_string(), map);
+		let ser = to_bytes(&v);
+		let tab = vec![0x10,
+		0x00, 0x05, 0x50, 0x6F, 0x69, 0x6E, 0x74,
+		0x00, 0x01, 0x31, 0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+		0x00, 0x00, 0x09];
+		assert_eq!(ser, tab);
+		assert_eq!(from_slice::<Value>(&ser).unwrap(), v)
+	}
+
+	#[test]
+	fn deserialize_typed_object_as_map() {
+		let v = [0x10,
+		0x00, 0x05, 0x50, 0x6F, 0x69, 0x6E, 0x74,
+		0x00, 0x01, 0x31, 0x01, 0x01,
+		0x00, 0x00, 0x09];
+		let v = from_slice::<value::Map<String, bool>>(&v).unwrap();
+		assert_eq!(v.get("1"), Some(&true))
+	}
+
+	#[test]
+	fn typed_object_round_trip() {
+		let mut map = value::Map::new();
+		map.insert("x".to_string(), Value::Number(1.));
+		map.insert("nested".to_string(), Value::TypedObject("Inner".to_string(), value::Map::new()));
+		let v = Value::StrictArray(vec![Value::TypedObject("Outer".to_string(), map), Value::Null]);
+		let ser = to_bytes(&v);
+		assert_eq!(from_slice::<Value>(&ser).unwrap(), v)
+	}
+
+	#[test]
+	fn serialize_struct_as_typed_object() {
+		let v = Connect{app: "live".to_string(), version: 42., secure: true};
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser).typed_objects(true);
+			v.serialize(serializer).unwrap();
+		}
+		let object = vec![0x10,
+		 0x00, 0x07, 0x43, 0x6F, 0x6E, 0x6E, 0x65, 0x63, 0x74,
+		 0x00, 0x03, 0x61, 0x70, 0x70, 0x02, 0x00, 0x04, 0x6C, 0x69, 0x76, 0x65,
+		 0x00, 0x07, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6F, 0x6E, 0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+		 0x00, 0x06, 0x73, 0x65, 0x63, 0x75, 0x72, 0x65, 0x01, 0x01,
+		 0x00, 0x00, 0x09];
+		assert_eq!(ser, object);
+		match from_slice::<Value>(&ser).unwrap() {
+			Value::TypedObject(name, m) => {
+				assert_eq!(name, "Connect");
+				assert_eq!(m.get("app"), Some(&Value::String("live".to_string())))
+			},
+			v => panic!("{:?}", v),
+		}
+	}
+
+	#[test]
+	fn serialize_struct_with_class_alias() {
+		let v = vec![Connect{app: "live".to_string(), version: 42., secure: true}];
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser).class_aliases(&[("Connect", "a.B")]);
+			v.serialize(serializer).unwrap();
+		}
+		assert_eq!(&ser[..11], &[0x0A, 0x00, 0x00, 0x00, 0x01, 0x10, 0x00, 0x03, 0x61, 0x2E, 0x42])
+	}
+
+	#[test]
+	fn deserialize_reference() {
+		let v = [0x0A, 0x00, 0x00, 0x00, 0x03,
+		0x03, 0x00, 0x01, 0x78, 0x0A, 0x00, 0x00, 0x00, 0x01, 0x00, 0x3F, 0xF0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x09,
+		0x07, 0x00, 0x01,
+		0x07, 0x00, 0x02];
+		let mut map = value::Map::new();
+		map.insert("x".to_string(), Value::StrictArray(vec![Value::Number(1.)]));
+		let object = Value::Object(map);
+		assert_eq!(from_slice::<Value>(&v).unwrap(), Value::StrictArray(vec![object.clone(), object, Value::StrictArray(vec![Value::Number(1.)])]))
+	}
+
+	#[test]
+	fn deserialize_reference_as_struct_field() {
+		let v = [0x0A, 0x00, 0x00, 0x00, 0x02,
+		0x03, 0x00, 0x01, 0x78, 0x00, 0x3F, 0xF0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x09,
+		0x07, 0x00, 0x01];
+		let v = from_slice::<Vec<std::collections::BTreeMap<String, f64>>>(&v).unwrap();
+		assert_eq!(v.len(), 2);
+		assert_eq!(v[0], v[1])
+	}
+
+	#[test]
+	fn deserialize_reference_out_of_range() {
+		let v = [0x0A, 0x00, 0x00, 0x00, 0x01, 0x07, 0x00, 0x01];
+		match from_slice::<Value>(&v) {
+			Err(Error::InvalidReference(1)) => {},
+			v => panic!("{:?}", v),
+		}
+	}
+
+	#[test]
+	fn deserialize_cyclic_reference() {
+		let v = [0x03, 0x00, 0x04, 0x73, 0x65, 0x6C, 0x66, 0x07, 0x00, 0x00, 0x00, 0x00, 0x09];
+		match from_slice::<Value>(&v) {
+			Err(Error::InvalidReference(0)) => {},
+			v => panic!("{:?}", v),
+		}
+	}
+
+	#[test]
+	fn serialize_reference_value() {
+		let ser = to_bytes(&Value::Reference(1));
+		assert_eq!(ser, vec![0x07, 0x00, 0x01])
+	}
+
+	fn to_bytes_with_references<T: Serialize>(v: &T) -> Vec<u8> {
+		let mut ser = Vec::with_capacity(128);
+		{
+			let serializer = self::Serializer::new(&mut ser).references(true);
+			v.serialize(serializer).unwrap();
+		}
+		ser
+	}
+
+	#[test]
+	fn serialize_with_references() {
+		let mut map = value::Map::new();
+		map.insert("x".to_string(), Value::StrictArray(vec![Value::Number(1.)]));
+		let object = Value::Object(map);
+		let v = Value::StrictArray(vec![object.clone(), object, Value::StrictArray(vec![Value::Number(1.)])]);
+		let ser = to_bytes_with_references(&v);
+		let tab = vec![0x0A, 0x00, 0x00, 0x00, 0x03,
+		0x03, 0x00, 0x01, 0x78, 0x0A, 0x00, 0x00, 0x00, 0x01, 0x00, 0x3F, 0xF0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x09,
+		0x07, 0x00, 0x01,
+		0x07, 0x00, 0x02];
+		assert_eq!(ser, tab);
+		assert_eq!(from_slice::<Value>(&ser).unwrap(), v)
+	}
+
+	#[test]
+	fn serialize_without_references() {
+		let object = Value::StrictArray(vec![Value::Number(1.)]);
+		let v = Value::StrictArray(vec![object.clone(), object]);
+		let ser = to_bytes(&v);
+		assert!(!ser.contains(&0x07))
+	}
+
+	#[test]
+	fn references_round_trip() {
+		let mut inner = value::Map::new();
+		inner.insert("name".to_string(), Value::String("a".repeat(100)));
+		let inner = Value::TypedObject("Inner".to_string(), inner);
+		let mut map = value::Map::new();
+		map.insert("a".to_string(), inner.clone());
+		map.insert("b".to_string(), Value::StrictArray(vec![inner.clone(), Value::Null]));
+		map.insert("c".to_string(), Value::ECMAArray(value::Map::new()));
+		map.insert("d".to_string(), Value::ECMAArray(value::Map::new()));
+		let v = Value::StrictArray(vec![Value::Object(map.clone()), inner, Value::Object(map)]);
+		let ser = to_bytes_with_references(&v);
+		assert!(ser.len() < to_bytes(&v).len());
+		assert_eq!(from_slice::<Value>(&ser).unwrap(), v)
+	}
+
+	#[test]
+	fn references_in_enum_and_struct() {
+		let v = vec![Status::Pair(1., true), Status::Info{level: "a".to_string()}, Status::Pair(1., true), Status::Info{level: "a".to_string()}];
+		let ser = to_bytes_with_references(&v);
+		assert_eq!(from_slice::<Vec<Status>>(&ser).unwrap(), v)
+	}
+
+	/// Writer accepting one byte per call, then failing once full.
+	struct SmallWriter {
+		buffer: Vec<u8>,
+		capacity: usize,
+	}
+
+	impl std::io::Write for SmallWriter {
+		fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+			if self.buffer.len() >= self.capacity {
+				return Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "full"));
+			}
+			self.buffer.push(buf[0]);
+			Ok(1)
+		}
+
+		fn flush(&mut self) -> std::io::Result<()> {
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn serialize_with_short_writes() {
+		let v = Connect{app: "live".to_string(), version: 42., secure: true};
+		let mut writer = SmallWriter{buffer: Vec::new(), capacity: 128};
+		{
+			let serializer = self::Serializer::new(&mut writer);
+			v.serialize(serializer).unwrap();
+		}
+		assert_eq!(writer.buffer, to_bytes(&v))
+	}
+
+	#[test]
+	fn serialize_number_in_few_writes() {
+		struct CountingWriter(usize);
+
+		impl std::io::Write for CountingWriter {
+			fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+				self.0 += 1;
+				Ok(buf.len())
+			}
+
+			fn flush(&mut self) -> std::io::Result<()> {
+				Ok(())
+			}
+		}
+
+		let mut writer = CountingWriter(0);
+		to_writer(&mut writer, &42.).unwrap();
+		assert_eq!(writer.0, 2);
+		let mut writer = CountingWriter(0);
+		to_writer(&mut writer, &Value::ECMAArray(value::Map::new())).unwrap();
+		assert_eq!(writer.0, 3)
+	}
+
+	#[test]
+	fn serialize_write_error() {
+		let mut map = value::Map::new();
+		map.insert("app".to_string(), Value::String("live".to_string()));
+		let v = Value::StrictArray(vec![Value::Object(map)]);
+		for capacity in 0..to_bytes(&v).len() {
+			let mut writer = SmallWriter{buffer: Vec::new(), capacity};
+			let serializer = self::Serializer::new(&mut writer);
+			match v.serialize(serializer) {
+				Err(ser::Error::IoError(ref err)) if err.kind() == std::io::ErrorKind::BrokenPipe => {},
+				v => panic!("{:?}", v),
+			}
+		}
+	}
+
+	#[test]
+	fn serialize_negative_integers() {
+		let minus_one = vec![0x00, 0xBF, 0xF0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+		assert_eq!(to_bytes(&-1i8), minus_one);
+		assert_eq!(to_bytes(&-1i16), minus_one);
+		assert_eq!(to_bytes(&-1i32), minus_one);
+		assert_eq!(to_bytes(&-1i64), minus_one);
+		assert_eq!(from_slice::<f64>(&to_bytes(&i32::MIN)).unwrap(), i32::MIN as f64)
+	}
+
+	#[test]
+	fn serialize_safe_integers() {
+		let max = (1i64 << 53) as f64;
+		assert_eq!(from_slice::<f64>(&to_bytes(&(1u64 << 53))).unwrap(), max);
+		assert_eq!(from_slice::<f64>(&to_bytes(&-(1i64 << 53))).unwrap(), -max)
+	}
+
+	fn to_bytes_with_policy<T: Serialize>(v: &T, policy: IntegerPolicy) -> Result<Vec<u8>, ser::Error> {
+		let mut ser = Vec::with_capacity(128);
+		v.serialize(self::Serializer::new(&mut ser).integer_policy(policy))?;
+		Ok(ser)
+	}
+
+	#[test]
+	fn serialize_large_integers() {
+		let v = (1u64 << 53) + 1;
+		let ser = to_bytes_with_policy(&v, IntegerPolicy::Lossy).unwrap();
+		assert_eq!(from_slice::<f64>(&ser).unwrap(), v as f64);
+		match to_bytes_with_policy(&vec![v], IntegerPolicy::Error) {
+			Err(err @ ser::Error::IntegerTooLarge) => assert_eq!(err.to_string(), "integer beyond 2^53, a Number can't hold it exactly"),
+			v => panic!("{:?}", v),
+		}
+		let ser = to_bytes_with_policy(&i64::MIN, IntegerPolicy::String).unwrap();
+		assert_eq!(from_slice::<String>(&ser).unwrap(), "-9223372036854775808");
+		let ser = to_bytes_with_policy(&vec![u64::MAX], IntegerPolicy::String).unwrap();
+		assert_eq!(from_slice::<Vec<String>>(&ser).unwrap(), vec!["18446744073709551615".to_string()])
+	}
+
+	#[test]
+	fn deserialize_integers() {
+		assert_eq!(from_slice::<u32>(&to_bytes(&42.)).unwrap(), 42);
+		assert_eq!(from_slice::<i32>(&to_bytes(&-1.)).unwrap(), -1);
+		assert_eq!(from_slice::<u8>(&to_bytes(&255.)).unwrap(), 255);
+		assert_eq!(from_slice::<i64>(&to_bytes(&i64::MIN)).unwrap(), i64::MIN);
+		assert_eq!(from_slice::<u64>(&to_bytes(&(1u64 << 53))).unwrap(), 1 << 53);
+		assert_eq!(from_slice::<Vec<u16>>(&to_bytes(&vec![1u16, 2, 3])).unwrap(), vec![1, 2, 3])
+	}
+
+	#[test]
+	fn deserialize_integer_out_of_range() {
+		match from_slice::<u8>(&to_bytes(&256.)) {
+			Err(Error::IntegerOutOfRange(v)) => assert_eq!(v, 256.),
+			v => panic!("{:?}", v),
+		}
+		match from_slice::<u32>(&to_bytes(&-1.)) {
+			Err(Error::IntegerOutOfRange(_)) => {},
+			v => panic!("{:?}", v),
+		}
+		match from_slice::<u64>(&to_bytes(&f64::INFINITY)) {
+			Err(Error::IntegerOutOfRange(_)) => {},
+			v => panic!("{:?}", v),
+		}
+	}
+
+	#[test]
+	fn deserialize_integer_with_fraction() {
+		match from_slice::<i32>(&to_bytes(&1.5)) {
+			Err(Error::IntegerWithFraction(v)) => assert_eq!(v, 1.5),
+			v => panic!("{:?}", v),
+		}
+	}
+
+	#[test]
+	fn serialize_char() {
+		assert_eq!(to_bytes(&'a'), vec![0x02, 0x00, 0x01, 0x61]);
+		assert_eq!(to_bytes(&'é'), vec![0x02, 0x00, 0x02, 0xC3, 0xA9]);
+		assert_eq!(to_bytes(&'😀'), vec![0x02, 0x00, 0x04, 0xF0, 0x9F, 0x98, 0x80])
+	}
+
+	#[test]
+	fn deserialize_char() {
+		assert_eq!(from_slice::<char>(&[0x02, 0x00, 0x01, 0x61]).unwrap(), 'a');
+		assert_eq!(from_slice::<char>(&to_bytes(&'😀')).unwrap(), '😀');
+		assert_eq!(from_slice::<Vec<char>>(&to_bytes(&vec!['a', 'é'])).unwrap(), vec!['a', 'é'])
+	}
+
+	#[test]
+	fn deserialize_char_from_longer_string() {
+		assert!(from_slice::<char>(&to_bytes(&"ab")).is_err());
+		assert!(from_slice::<char>(&to_bytes(&"")).is_err())
+	}
+
+	#[test]
+	fn serialize_bytes() {
+		let v = serde::bytes::Bytes::new(&[0, 255]);
+		let ser = to_bytes(&v);
+		let tab = vec![0x0A, 0x00, 0x00, 0x00, 0x02,
+		0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+		0x00, 0x40, 0x6F, 0xE0, 0x00, 0x00, 0x00, 0x00, 0x00];
+		assert_eq!(ser, tab);
+		assert_eq!(from_slice::<serde::bytes::ByteBuf>(&ser).unwrap(), serde::bytes::ByteBuf::from(vec![0, 255]))
+	}
+
+	#[test]
+	fn deserialize_bytes_out_of_range() {
+		let ser = to_bytes(&vec![1., 256.]);
+		assert!(from_slice::<serde::bytes::ByteBuf>(&ser).is_err());
+		let ser = to_bytes(&vec![Value::String("a".to_string())]);
+		assert!(from_slice::<serde::bytes::ByteBuf>(&ser).is_err())
+	}
+
+	#[test]
+	fn to_writer_and_from_reader() {
+		let v = Value::StrictArray(vec![Value::Number(42.), Value::String("live".to_string())]);
+		let mut writer = Vec::new();
+		to_writer(&mut writer, &v).unwrap();
+		assert_eq!(writer, to_vec(&v).unwrap());
+		let reader = std::io::Cursor::new(writer);
+		assert_eq!(from_reader::<_, Value>(reader).unwrap(), v)
+	}
+
+	#[test]
+	fn to_writer_error() {
+		let mut writer = SmallWriter{buffer: Vec::new(), capacity: 2};
+		assert!(to_writer(&mut writer, &"live").is_err())
+	}
+
+	/// Reader giving back one byte per call.
+	struct OneByteReader<R>(R);
+
+	impl<R: std::io::Read> std::io::Read for OneByteReader<R> {
+		fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+			self.0.read(&mut buf[..1])
+		}
+	}
+
+	#[test]
+	fn deserialize_from_io_reader() {
+		let mut map = value::Map::new();
+		map.insert("app".to_string(), Value::String("live".to_string()));
+		map.insert("long".to_string(), Value::LongString("a".repeat(10000)));
+		let object = Value::Object(map);
+		let v = Value::StrictArray(vec![object.clone(), object, Value::Number(42.)]);
+		let ser = to_bytes_with_references(&v);
+		assert_eq!(from_reader::<_, Value>(std::io::Cursor::new(&ser)).unwrap(), v);
+		assert_eq!(from_reader::<_, Value>(std::io::BufReader::new(OneByteReader(std::io::Cursor::new(&ser)))).unwrap(), v)
+	}
+
+	#[test]
+	fn deserialize_from_io_reader_in_chunks() {
+		struct CountingReader<R> {
+			reader: R,
+			reads: usize,
+		}
+
+		impl<R: std::io::Read> std::io::Read for CountingReader<R> {
+			fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+				self.reads += 1;
+				self.reader.read(buf)
+			}
+		}
+
+		let v = Value::LongString("a".repeat(100000));
+		let ser = to_bytes(&v);
+		let mut reader = std::io::BufReader::new(CountingReader{reader: std::io::Cursor::new(&ser), reads: 0});
+		assert_eq!(from_reader::<_, Value>(&mut reader).unwrap(), v);
+		assert!(reader.get_ref().reads <= ser.len() / 8192 + 2)
+	}
+
+	#[test]
+	fn deserialize_successive_values_from_io_reader() {
+		let mut ser = to_bytes(&"connect");
+		ser.extend(to_bytes(&1.));
+		ser.extend(to_bytes(&Value::Object(value::Map::new())));
+		let mut de = Deserializer::new_from_reader(std::io::BufReader::new(OneByteReader(std::io::Cursor::new(ser))));
+		assert_eq!(String::deserialize(&mut de).unwrap(), "connect");
+		assert_eq!(u32::deserialize(&mut de).unwrap(), 1);
+		assert_eq!(Value::deserialize(&mut de).unwrap(), Value::Object(value::Map::new()));
+		assert!(Value::deserialize(&mut de).is_err())
+	}
+
+	#[test]
+	fn deserialize_from_borrowed_io_reader() {
+		let mut ser = to_bytes(&"a");
+		ser.extend(to_bytes(&"b"));
+		let mut cursor = std::io::Cursor::new(&ser);
+		assert_eq!(from_reader::<_, String>(&mut cursor).unwrap(), "a");
+		assert_eq!(from_reader::<_, String>(&mut cursor).unwrap(), "b");
+		let mut reader = std::io::BufReader::new(std::io::Cursor::new(&ser));
+		assert_eq!(from_reader::<_, String>(&mut reader).unwrap(), "a");
+		assert_eq!(from_reader::<_, String>(&mut reader).unwrap(), "b")
+	}
+
+	#[test]
+	fn deserialize_many_values_from_io_reader() {
+		let value = |i: usize| {
+			let mut map = value::Map::new();
+			map.insert("app".to_string(), Value::String(i.to_string()));
+			let object = Value::Object(map);
+			Value::StrictArray(vec![object.clone(), object])
+		};
+		let mut ser = Vec::new();
+		for i in 0..1000 {
+			ser.extend(to_bytes_with_references(&value(i)));
+		}
+		let mut de = Deserializer::new_from_reader(std::io::Cursor::new(ser));
+		for i in 0..1000 {
+			assert_eq!(Value::deserialize(&mut de).unwrap(), value(i));
+			assert!(de.reader.buffered() <= 64)
+		}
+	}
+
+	#[test]
+	fn deserialize_from_failing_io_reader() {
+		struct FailingReader;
+
+		impl std::io::Read for FailingReader {
+			fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+				Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset"))
+			}
+		}
+
+		match from_reader::<_, Value>(std::io::BufReader::new(FailingReader)) {
+			Err(Error::IoError(ref err)) if err.kind() == std::io::ErrorKind::ConnectionReset => {},
+			v => panic!("{:?}", v),
+		}
+		let err = from_reader::<_, Value>(std::io::BufReader::new(FailingReader)).unwrap_err();
+		#[allow(deprecated)]
+		let cause = std::error::Error::cause(&err);
+		assert_eq!(cause.unwrap().to_string(), "reset")
+	}
+
+	#[test]
+	fn stream_deserializer() {
+		let mut map = value::Map::new();
+		map.insert("app".to_string(), Value::String("live".to_string()));
+		let mut ser = to_bytes(&"connect");
+		ser.extend(to_bytes(&1.));
+		ser.extend(to_bytes(&Value::Object(map.clone())));
+		ser.extend(to_bytes(&Value::Null));
+		let values = Deserializer::new_from_slice(&ser).into_stream::<Value>().collect::<Result<Vec<_>, _>>().unwrap();
+		assert_eq!(values, vec![
+			(0, Value::String("connect".to_string())),
+			(10, Value::Number(1.)),
+			(19, Value::Object(map)),
+			(35, Value::Null),
+		]);
+		let mut stream = Deserializer::new_from_reader(std::io::BufReader::new(OneByteReader(std::io::Cursor::new(&ser)))).into_stream::<Value>();
+		assert_eq!(stream.nth(3).unwrap().unwrap(), (35, Value::Null));
+		assert!(stream.next().is_none())
+	}
+
+	#[test]
+	fn stream_deserializer_of_empty_input() {
+		assert!(Deserializer::new_from_slice(&[]).into_stream::<Value>().next().is_none())
+	}
+
+	#[test]
+	fn stream_deserializer_stops_on_error() {
+		let mut ser = to_bytes(&1.);
+		ser.extend(to_bytes(&"connect"));
+		ser.extend(to_bytes(&2.));
+		let mut stream = Deserializer::new_from_slice(&ser).into_stream::<f64>();
+		assert_eq!(stream.next().unwrap().unwrap(), (0, 1.));
+		assert!(stream.next().unwrap().is_err());
+		assert!(stream.next().is_none())
+	}
+
+	#[test]
+	fn stream_deserializer_truncated_value() {
+		let mut ser = to_bytes(&1.);
+		ser.extend(&[0x00, 0x40]);
+		let mut stream = Deserializer::new_from_slice(&ser).into_stream::<f64>();
+		assert_eq!(stream.next().unwrap().unwrap(), (0, 1.));
+		match stream.next() {
+			Some(Err(Error::UnexpectedEOF(11))) => {},
+			v => panic!("{:?}", v),
+		}
+	}
+
+	#[test]
+	fn stream_deserializer_references_per_value() {
+		let object = Value::StrictArray(vec![Value::Number(1.)]);
+		let v = Value::StrictArray(vec![object.clone(), object]);
+		let mut ser = to_bytes_with_references(&v);
+		ser.extend(to_bytes_with_references(&v));
+		let values = Deserializer::new_from_slice(&ser).into_stream::<Value>().map(|v| v.unwrap().1).collect::<Vec<_>>();
+		assert_eq!(values, vec![v.clone(), v])
+	}
+
+	#[test]
+	fn serialize_flattened_with_references() {
+		let mut map = value::Map::new();
+		map.insert("app".to_string(), Value::String("live".to_string()));
+		let object = Value::Object(map);
+		let pair = Value::StrictArray(vec![object.clone(), object.clone()]);
+		let v = ("connect".to_string(), object.clone(), object.clone(), pair.clone());
+		let mut ser = Vec::with_capacity(128);
+		v.serialize(self::Serializer::new(&mut ser).flatten(true).references(true)).unwrap();
+		assert_eq!(ser[26], 0x03); // The second object is written again, not as a reference
+		assert_eq!(&ser[ser.len() - 3..], &[0x07, 0x00, 0x01]); // References within a value are kept
+		let values = Deserializer::new_from_slice(&ser).into_stream::<Value>().map(|v| v.unwrap().1).collect::<Vec<_>>();
+		assert_eq!(values, vec![Value::String("connect".to_string()), object.clone(), object, pair])
+	}
+
+	#[test]
+	fn deserialize_unknown_marker() {
+		let v = [0x0A, 0x00, 0x00, 0x00, 0x01, 0x11];
+		match from_slice::<Value>(&v) {
+			Err(Error::UnknownMarker{marker: 0x11, offset: 5}) => {},
+			v => panic!("{:?}", v),
+		}
+		match from_slice::<String>(&[0xFF]) {
+			Err(Error::UnknownMarker{marker: 0xFF, offset: 0}) => {},
+			v => panic!("{:?}", v),
+		}
+	}
+
+	#[test]
+	fn error_display() {
+		let err = from_slice::<Value>(&[0x02, 0x00, 0x05, 0x61]).unwrap_err();
+		assert_eq!(err.to_string(), "unexpected end of input at offset 4");
+		let err = from_slice::<Value>(&[0x0A, 0x00, 0x00, 0x00, 0x01, 0x11]).unwrap_err();
+		assert_eq!(err.to_string(), "unknown marker 0x11 at offset 5");
+		let err = from_slice::<(String, f64)>(&to_bytes(&(1., 2.))).unwrap_err();
+		assert_eq!(err.to_string(), "invalid type: floating point `1`, expected a string");
+		let err = from_slice::<Publish>(&to_bytes(&Value::Object(value::Map::new()))).unwrap_err();
+		assert_eq!(err.to_string(), "missing field `name`")
+	}
+
+	#[test]
+	fn deserialize_marker_not_starting_a_value() {
+		for &marker in &[0x04, 0x09, 0x0E] {
+			match from_slice::<Value>(&[marker]) {
+				Err(Error::UnknownMarker{marker: m, offset: 0}) if m == marker => {},
+				v => panic!("{:?}", v),
+			}
+			match from_slice::<Option<Value>>(&[0x0A, 0x00, 0x00, 0x00, 0x01, marker]) {
+				Err(Error::UnknownMarker{marker: m, offset: 5}) if m == marker => {},
+				v => panic!("{:?}", v),
+			}
+		}
+		assert_eq!(from_slice::<Value>(&[0x0D]).unwrap(), Value::Undefined);
+		assert_eq!(from_slice::<Option<f64>>(&[0x0D]).unwrap(), None)
+	}
+
+	#[test]
+	fn deserialize_trailing_bytes() {
+		let v = [0x00, 0x40, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05];
+		match from_slice::<f64>(&v) {
+			Err(Error::TrailingBytes(9)) => {},
+			v => panic!("{:?}", v),
+		}
+		assert_eq!(from_slice_lenient::<f64>(&v).unwrap(), 42.)
+	}
+
+	#[test]
+	fn deserialize_invalid_utf8() {
+		let v = [0x0A, 0x00, 0x00, 0x00, 0x01, 0x02, 0x00, 0x02, 0xC3, 0x28];
+		assert!(matches!(from_slice::<Value>(&v), Err(Error::InvalidUtf8(8))));
+		let v = [0x03, 0x00, 0x01, 0xFF, 0x05, 0x00, 0x00, 0x09];
+		assert!(matches!(from_slice::<Value>(&v), Err(Error::InvalidUtf8(3))))
+	}
+
+	#[test]
+	fn deserialize_truncated_at_marker() {
+		// The offset is where the missing marker would be
+		assert!(matches!(from_slice::<Value>(&[]), Err(Error::UnexpectedEOF(0))));
+		let v = [0x0A, 0x00, 0x00, 0x00, 0x01];
+		assert!(matches!(from_slice::<Value>(&v), Err(Error::UnexpectedEOF(5))));
+		let v = [0x03, 0x00, 0x01, 0x61];
+		assert!(matches!(from_slice::<Value>(&v), Err(Error::UnexpectedEOF(4))));
+		let v = [0x0A, 0x00, 0x00, 0x00, 0x01];
+		assert!(matches!(from_reader::<_, Value>(std::io::BufReader::new(OneByteReader(std::io::Cursor::new(v)))), Err(Error::UnexpectedEOF(5))))
+	}
+
+	#[test]
+	fn deserialize_truncated_key() {
+		let v = [0x03, 0x00, 0x05, 0x61];
+		assert!(matches!(from_slice::<Value>(&v), Err(Error::UnexpectedEOF(4))));
+		let v = [0x03, 0x00];
+		assert!(matches!(from_slice::<Value>(&v), Err(Error::UnexpectedEOF(2))))
+	}
+
+	#[test]
+	fn deserialize_too_deep() {
+		let mut v = Vec::new();
+		for _ in 0..100000 {
+			v.extend(&[0x0A, 0x00, 0x00, 0x00, 0x01]);
+		}
+		assert!(matches!(from_slice::<Value>(&v), Err(Error::DepthLimitExceeded)))
+	}
+
+	#[test]
+	fn deserialize_reference_bomb() {
+		// Each element holds two references to the previous one
+		let mut v = vec![0x0A, 0x00, 0x00, 0x00, 0x40, 0x0A, 0x00, 0x00, 0x00, 0x00];
+		for index in 1..0x40 {
+			v.extend(&[0x0A, 0x00, 0x00, 0x00, 0x02, 0x07, 0x00, index, 0x07, 0x00, index]);
+		}
+		assert!(matches!(from_slice::<Value>(&v), Err(Error::ReferenceLimitExceeded)))
+	}
+
+	#[test]
+	fn deserialize_many_values_with_references() {
+		// The replay budget is per value, a long stream doesn't run out of it
+		let value = [0x0A, 0x00, 0x00, 0x00, 0x02, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x01];
+		let mut v = Vec::new();
+		for _ in 0..70000 {
+			v.extend(&value);
+		}
+		let expected = from_slice::<Value>(&value).unwrap();
+		let mut count = 0;
+		for item in Deserializer::new_from_slice(&v).into_stream::<Value>() {
+			assert_eq!(item.unwrap().1, expected);
+			count += 1;
+		}
+		assert_eq!(count, 70000);
+		let mut de = Deserializer::new_from_slice(&v);
+		for _ in 0..70000 {
+			assert_eq!(Value::deserialize(&mut de).unwrap(), expected);
+		}
+	}
+
+	/// Xorshift generator, the fuzz tests stay reproducible.
+	struct Rng(u64);
+
+	impl Rng {
+		fn next(&mut self) -> u64 {
+			self.0 ^= self.0 << 13;
+			self.0 ^= self.0 >> 7;
+			self.0 ^= self.0 << 17;
+			self.0
+		}
+
+		fn below(&mut self, n: usize) -> usize {
+			(self.next() % n as u64) as usize
+		}
+	}
+
+	/// Decode the input as several types, only a panic fails.
+	fn decode_all(v: &[u8]) {
+		let _ = from_slice::<Value>(v);
+		let _ = from_slice_lenient::<Value>(v);
+		let _ = from_slice::<Vec<Status>>(v);
+		let _ = from_slice::<TypedObject<value::Map<String, Value>>>(v);
+		let _ = from_slice::<EcmaArray<value::Map<String, f64>>>(v);
+		let _ = from_slice::<(String, u32, Option<Date>)>(v);
+		let _ = from_slice::<Vec<char>>(v);
+		let _ = from_slice::<serde::bytes::ByteBuf>(v);
+		let _ = Deserializer::new_from_slice(v).into_stream::<Value>().count();
+		let _ = from_reader::<_, Value>(std::io::BufReader::new(OneByteReader(std::io::Cursor::new(v))));
+	}
+
+	#[test]
+	fn fuzz_deserialize() {
+		let mut map = value::Map::new();
+		map.insert("app".to_string(), Value::String("live".to_string()));
+		map.insert("flashVer".to_string(), Value::LongString("FMLE/3.0".to_string()));
+		map.insert("tab".to_string(), Value::ECMAArray(map.clone()));
+		let object = Value::TypedObject("Connect".to_string(), map);
+		let corpus = vec![
+			to_bytes_with_references(&Value::StrictArray(vec![object.clone(), object.clone(), Value::Number(1.)])),
+			to_bytes(&Value::StrictArray(vec![Value::Date(Date::from_millis(0.)), Value::XMLDocument("<a/>".to_string()), Value::Undefined])),
+			to_bytes(&vec![Status::Idle, Status::Code(1.), Status::Pair(2., true), Status::Info{level: "status".to_string()}]),
+			to_bytes(&("connect".to_string(), 1u32, Some(Date::from_millis(1.)))),
+			to_bytes(&serde::bytes::Bytes::new(&[1, 2, 3])),
+		];
+		let mut rng = Rng(0x2545F4914F6CDD1D);
+		for _ in 0..500 {
+			let len = rng.below(64);
+			let v = (0..len).map(|_| rng.below(0x12) as u8).collect::<Vec<_>>();
+			decode_all(&v);
+		}
+		for v in &corpus {
+			decode_all(v);
+			for len in 0..v.len() {
+				decode_all(&v[..len]);
+			}
+			for _ in 0..300 {
+				let mut v = v.clone();
+				for _ in 0..1 + rng.below(4) {
+					let index = rng.below(v.len());
+					match rng.below(3) {
+						0 => v[index] = rng.next() as u8,
+						1 => v[index] ^= 1 << rng.below(8),
+						_ => v.insert(index, rng.below(0x12) as u8),
+					}
+				}
+				decode_all(&v);
+			}
+		}
+	}
+
+
+	#[test]
+	fn deserialize_option() {
+		assert_eq!(from_slice::<Option<f64>>(&[0x05]).unwrap(), None);
+		assert_eq!(from_slice::<Option<f64>>(&[0x06]).unwrap(), None);
+		assert_eq!(from_slice::<Option<f64>>(&to_bytes(&42.)).unwrap(), Some(42.));
+		assert_eq!(from_slice::<Option<Value>>(&to_bytes(&Value::Bool(true))).unwrap(), Some(Value::Bool(true)));
+		let v = (Some("live".to_string()), None::<f64>, Some(1u32));
+		assert_eq!(from_slice::<(Option<String>, Option<f64>, Option<u32>)>(&to_bytes(&v)).unwrap(), v);
+		assert!(matches!(from_slice::<Option<f64>>(&[]), Err(Error::UnexpectedEOF(0))));
+		match from_slice::<Option<f64>>(&[0x01, 0x01]) {
+			Err(Error::Custom(ref msg)) => assert_eq!(msg, "invalid type: boolean `true`, expected f64"),
+			v => panic!("{:?}", v),
+		}
+	}
+
+	struct Play {
+		name: String,
+		start: Option<f64>,
+		duration: Option<f64>,
+	}
+
+	impl Serialize for Play {
+		fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+			use serde::ser::SerializeStruct;
+			let mut s = serializer.serialize_struct("Play", 3)?;
+			s.serialize_field("name", &self.name)?;
+			s.serialize_field("start", &self.start)?;
+			s.serialize_field("duration", &self.duration)?;
+			s.end()
+		}
+	}
+
+	#[test]
+	fn serialize_none_fields() {
+		let v = Play{name: "stream".to_string(), start: None, duration: Some(10.)};
+		let mut map = value::Map::new();
+		map.insert("name".to_string(), Value::String("stream".to_string()));
+		map.insert("duration".to_string(), Value::Number(10.));
+
+		let mut ser = Vec::with_capacity(128);
+		v.serialize(self::Serializer::new(&mut ser).skip_none_fields(true)).unwrap();
+		assert_eq!(from_slice::<Value>(&ser).unwrap(), Value::Object(map.clone()));
+
+		let mut all = map.clone();
+		all.insert("start".to_string(), Value::Undefined);
+		assert_eq!(from_slice::<Value>(&to_bytes(&v)).unwrap(), Value::Object(all.clone()));
+
+		// Nested structs follow the setting, ECMA arrays keep every field
+		let mut ser = Vec::with_capacity(128);
+		(&v, EcmaArray(&v)).serialize(self::Serializer::new(&mut ser).skip_none_fields(true)).unwrap();
+		assert_eq!(from_slice::<Value>(&ser).unwrap(), Value::StrictArray(vec![Value::Object(map), Value::ECMAArray(all)]));
+	}
+
+
+	/// Laid out like the code generated by `#[derive(Deserialize)]`.
+	#[derive(Debug, PartialEq)]
+	struct Publish {
+		name: String,
+		kind: String,
+		start: Option<f64>,
+	}
+
+	#[derive(Debug, PartialEq)]
+	struct StrictPublish(Publish);
+
+	fn deserialize_publish<D: serde::Deserializer>(deserializer: D, deny_unknown_fields: bool) -> Result<Publish, D::Error> {
+		use std::fmt;
+		use serde::de;
+
+		enum Field { Name, Kind, Start, Ignore }
+
+		struct FieldVisitor(bool);
+
+		impl de::Visitor for FieldVisitor {
+			type Value = Field;
+
+			fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+				f.write_str("field identifier")
+			}
+
+			fn visit_str<E: de::Error>(self, v: &str) -> Result<Field, E> {
+				match v {
+					"name" => Ok(Field::Name),
+					"kind" => Ok(Field::Kind),
+					"start" => Ok(Field::Start),
+					_ if self.0 => Err(de::Error::unknown_field(v, FIELDS)),
+					_ => Ok(Field::Ignore),
+				}
+			}
+		}
+
+		struct FieldSeed(bool);
+
+		impl de::DeserializeSeed for FieldSeed {
+			type Value = Field;
+
+			fn deserialize<D: serde::Deserializer>(self, deserializer: D) -> Result<Field, D::Error> {
+				deserializer.deserialize_struct_field(FieldVisitor(self.0))
+			}
+		}
+
+		struct PublishVisitor(bool);
+
+		impl de::Visitor for PublishVisitor {
+			type Value = Publish;
+
+			fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+				f.write_str("struct Publish")
+			}
+
+			fn visit_map<V: de::MapVisitor>(self, mut visitor: V) -> Result<Publish, V::Error> {
+				let (mut name, mut kind, mut start) = (None, None, None);
+				while let Some(key) = visitor.visit_key_seed(FieldSeed(self.0))? {
+					match key {
+						Field::Name if name.is_some() => return Err(de::Error::duplicate_field("name")),
+						Field::Name => name = Some(visitor.visit_value()?),
+						Field::Kind => kind = Some(visitor.visit_value()?),
+						Field::Start => start = Some(visitor.visit_value()?),
+						Field::Ignore => { visitor.visit_value::<de::impls::IgnoredAny>()?; },
+					}
+				}
+				Ok(Publish {
+					name: name.ok_or_else(|| de::Error::missing_field("name"))?,
+					kind: kind.unwrap_or_else(|| "live".to_string()),
+					start: start.unwrap_or(None),
+				})
+			}
+		}
+
+		const FIELDS: &[&str] = &["name", "kind", "start"];
+		deserializer.deserialize_struct("Publish", FIELDS, PublishVisitor(deny_unknown_fields))
+	}
+
+	impl Deserialize for Publish {
+		fn deserialize<D: serde::Deserializer>(deserializer: D) -> Result<Publish, D::Error> {
+			deserialize_publish(deserializer, false)
+		}
+	}
+
+	impl Deserialize for StrictPublish {
+		fn deserialize<D: serde::Deserializer>(deserializer: D) -> Result<StrictPublish, D::Error> {
+			deserialize_publish(deserializer, true).map(StrictPublish)
+		}
+	}
+
+	fn object(fields: &[(&str, Value)]) -> Value {
+		Value::Object(fields.iter().map(|(k, v)| (k.to_string(), v.clone())).collect())
+	}
+
+	#[test]
+	fn deserialize_struct_fields() {
+		let publish = Publish{name: "stream".to_string(), kind: "record".to_string(), start: Some(0.)};
+		let v = object(&[("name", Value::String("stream".to_string())), ("kind", Value::String("record".to_string())), ("start", Value::Number(0.))]);
+		assert_eq!(from_slice::<Publish>(&to_bytes(&v)).unwrap(), publish);
+		let v = Value::TypedObject("Publish".to_string(), match v { Value::Object(map) => map, _ => unreachable!() });
+		assert_eq!(from_slice::<Publish>(&to_bytes(&v)).unwrap(), publish);
+
+		let v = object(&[("name", Value::String("stream".to_string())), ("start", Value::Null)]);
+		let publish = Publish{name: "stream".to_string(), kind: "live".to_string(), start: None};
+		assert_eq!(from_slice::<Publish>(&to_bytes(&v)).unwrap(), publish);
+		let v = object(&[("name", Value::String("stream".to_string()))]);
+		assert_eq!(from_slice::<Publish>(&to_bytes(&v)).unwrap(), publish);
+
+		let v = object(&[("kind", Value::String("live".to_string()))]);
+		assert!(matches!(from_slice::<Publish>(&to_bytes(&v)), Err(Error::MissingField("name"))));
+		let mut ser = vec![0x03];
+		for _ in 0..2 {
+			ser.extend(&[0x00, 0x04, 0x6E, 0x61, 0x6D, 0x65, 0x02, 0x00, 0x01, 0x61]);
+		}
+		ser.extend(&[0x00, 0x00, 0x09]);
+		assert!(matches!(from_slice::<Publish>(&ser), Err(Error::DuplicateField("name"))));
+		assert!(matches!(from_slice::<Publish>(&to_bytes(&42.)), Err(Error::SyntaxError)));
+	}
+
+	#[test]
+	fn deserialize_struct_unknown_fields() {
+		let publish = Publish{name: "stream".to_string(), kind: "live".to_string(), start: None};
+		let extra = Value::StrictArray(vec![Value::Number(1.), object(&[("a", Value::Null)])]);
+		let v = object(&[("name", Value::String("stream".to_string())), ("extra", extra)]);
+		let ser = to_bytes(&v);
+		assert_eq!(from_slice::<Publish>(&ser).unwrap(), publish);
+		match from_slice::<StrictPublish>(&ser) {
+			Err(Error::UnknownField(field)) => assert_eq!(field, "extra"),
+			v => panic!("{:?}", v),
+		}
+
+		// References to the skipped field still resolve
+		let v = (v.clone(), v);
+		let ser = to_bytes_with_references(&v);
+		assert_eq!(from_slice::<(Publish, Value)>(&ser).unwrap(), (publish, v.1));
+	}
+
 }
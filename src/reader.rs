@@ -1,3 +1,6 @@
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
 use error::Error;
 
 pub trait Read {
@@ -7,6 +10,8 @@ pub trait Read {
     fn discard(&mut self);
     fn position(&self) -> Position;
     fn peek_position(&self) -> Position;
+    /// Number of bytes read so far.
+    fn offset(&self) -> usize;
 }
 
 #[derive(Clone, Copy)]
@@ -26,7 +31,7 @@ impl<'a> SliceReader<'a> {
 
     pub fn new(slice: &'a [u8]) -> Self {
         SliceReader {
-            slice: slice,
+            slice,
             index: 0,
             position : Position{line: 0, column: 0}
         }
@@ -70,4 +75,121 @@ impl<'a> Read for SliceReader<'a> {
     fn peek_position(&self) -> Position {
         Position{line: self.position.line, column: self.position.column}
     }
-}
\ No newline at end of file
+
+    fn offset(&self) -> usize {
+        self.index
+    }
+}
+
+/// Reader over any `std::io::BufRead`, such as a `std::io::BufReader`
+/// around a `TcpStream`.
+///
+/// Bytes are consumed from the reader's buffer as they are decoded, so
+/// nothing past the decoded values is taken and the next value can be read
+/// from the same reader. Copies share the bytes read so far, they are kept
+/// while a copy may go back to them.
+pub struct IoReader<R> {
+    buffer: Rc<RefCell<Buffer<R>>>,
+    index: usize,
+    position: Position,
+}
+
+struct Buffer<R> {
+    reader: R,
+    bytes: Vec<u8>,
+    start: usize, // Offset of the first byte kept
+    consumed: usize, // Offset of the first byte not consumed from the reader
+}
+
+impl<R: io::BufRead> IoReader<R> {
+
+    pub fn new(reader: R) -> Self {
+        IoReader {
+            buffer: Rc::new(RefCell::new(Buffer{reader, bytes: Vec::new(), start: 0, consumed: 0})),
+            index: 0,
+            position : Position{line: 0, column: 0}
+        }
+    }
+
+    /// Drop the bytes before the current index if no copy can go back to them.
+    fn drop_consumed(&self) {
+        if Rc::strong_count(&self.buffer) == 1 {
+            let mut buffer = self.buffer.borrow_mut();
+            let dropped = (self.index - buffer.start).min(buffer.bytes.len());
+            buffer.bytes.drain(..dropped);
+            buffer.start += dropped;
+        }
+    }
+
+    /// Byte at the current index, taking it from the reader's buffer if it
+    /// isn't kept yet. It stays in the reader until the index moves past it.
+    fn fill(&mut self) -> Result<Option<u8>, Error> {
+        self.drop_consumed();
+        let mut buffer = self.buffer.borrow_mut();
+        if self.index >= buffer.start + buffer.bytes.len() {
+            let byte = loop {
+                match buffer.reader.fill_buf() {
+                    Ok(available) => break available.first().cloned(),
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {},
+                    Err(err) => return Err(Error::from(err)),
+                }
+            };
+            buffer.bytes.extend(byte);
+        }
+        Ok(buffer.bytes.get(self.index - buffer.start).cloned())
+    }
+
+    /// Move past the current byte, consuming it from the reader if no copy
+    /// did yet.
+    fn advance(&mut self) {
+        self.index += 1;
+        let mut buffer = self.buffer.borrow_mut();
+        if self.index > buffer.consumed {
+            let nb = self.index - buffer.consumed;
+            buffer.reader.consume(nb);
+            buffer.consumed = self.index;
+        }
+    }
+
+    /// Number of bytes kept in the buffer.
+    #[cfg(test)]
+    pub(crate) fn buffered(&self) -> usize {
+        self.buffer.borrow().bytes.len()
+    }
+}
+
+impl<R: io::BufRead> Read for IoReader<R> {
+    fn copy(&self) -> Self {
+        self.drop_consumed(); // Neither copy will go back before the current index
+        IoReader{buffer: self.buffer.clone(), index: self.index, position: self.position}
+    }
+
+    fn next(&mut self) -> Result<Option<u8>, Error> {
+        let c = self.fill()?;
+        if c.is_some() {
+            self.position.column += 1;
+            self.advance();
+        }
+        Ok(c)
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, Error> {
+        self.fill()
+    }
+
+    fn discard(&mut self) {
+        self.advance();
+    }
+
+    fn position(&self) -> Position {
+        Position{line: self.position.line, column: self.position.column}
+    }
+
+    fn peek_position(&self) -> Position {
+        Position{line: self.position.line, column: self.position.column}
+    }
+
+    fn offset(&self) -> usize {
+        self.index
+    }
+}
@@ -1,61 +1,275 @@
 extern crate byteorder;
 
+use std::cell::RefCell;
 use std::char;
+use std::collections::HashMap;
 use std::error;
 use std::fmt;
 use std::io;
 use std::str;
+use std::rc::Rc;
 use std::result;
 use self::byteorder::{BigEndian, WriteBytesExt};
 
 use serde::ser;
+use value::{Marker, DATE, ECMA_ARRAY, LONG_STRING, REFERENCE, TYPED_OBJECT, UNDEFINED, XML_DOCUMENT};
 
 pub struct Serializer<'a, W : 'a> {
 	pub writer: &'a mut W,
+	flatten: bool,
+	typed_objects: bool,
+	class_aliases: &'a [(&'a str, &'a str)],
+	integer_policy: IntegerPolicy,
+	skip_none_fields: bool,
+	references: Option<Rc<RefCell<References>>>, // Shared by all the nested serializers
+	marker: Option<Marker>, // Marker forced by a wrapper type for the next value
+}
+
+/// How integers a Number can't hold exactly, beyond 2^53, are written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntegerPolicy {
+	/// Rounded to the nearest Number.
+	Lossy,
+	/// Rejected with `Error::IntegerTooLarge`.
+	Error,
+	/// Written as a String holding the decimal value.
+	String,
+}
+
+/// Largest magnitude up to which every integer is exactly a Number.
+const MAX_SAFE_INTEGER: u64 = 1 << 53;
+
+/// Complex values written so far, numbered in the order the decoder sees
+/// their markers, and keyed by their encoding without references.
+#[derive(Default)]
+struct References {
+	count: u32,
+	indices: HashMap<Vec<u8>, u16>,
 }
 
 impl<'a, W : io::Write> Serializer<'a, W> {
-	fn write_str(self, v: &str) -> () {
-		let array = [(v.len() / 256) as u8, (v.len() % 256) as u8];
-		let _ = self.writer.write(&array);
-		let _ = self.writer.write(v.as_bytes());
+	/// Write a string with its u16 length, as used by String values and object keys.
+	fn write_str(&mut self, v: &str) -> Result<(), self::Error> {
+		if v.len() > u16::MAX as usize {
+			return Err(Error::InvalidSize);
+		}
+		self.writer.write_u16::<BigEndian>(v.len() as u16)?;
+		self.writer.write_all(v.as_bytes())?;
+		Ok(())
+	}
+
+	/// Write a string with its u32 length, as used by LongString values.
+	fn write_long_str(&mut self, v: &str) -> Result<(), self::Error> {
+		if v.len() > u32::MAX as usize {
+			return Err(Error::InvalidSize);
+		}
+		self.writer.write_u32::<BigEndian>(v.len() as u32)?;
+		self.writer.write_all(v.as_bytes())?;
+		Ok(())
+	}
+
+	fn write_strict_array_header(&mut self, len: u32) -> Result<(), self::Error> {
+		self.count_reference();
+		self.writer.write_all(&[0x0A])?;
+		self.writer.write_u32::<BigEndian>(len)?;
+		Ok(())
+	}
+
+	/// Open the single-key object wrapping an externally tagged enum variant.
+	fn write_variant_key(&mut self, variant: &str) -> Result<(), self::Error> {
+		self.count_reference();
+		self.writer.write_all(&[0x03])?;
+		self.write_str(variant)
 	}
 
 	pub fn new(writer: &'a mut W) -> Self {
-		Serializer{writer: writer}
+		Serializer{writer, flatten: false, typed_objects: false, class_aliases: &[], integer_policy: IntegerPolicy::Lossy, skip_none_fields: false, references: None, marker: None}
 	}
-}
 
-pub struct SerializeSeq {
+	/// Write a top-level tuple or tuple struct as its elements back to back,
+	/// without the strict array header. This is the layout of an RTMP command
+	/// message body. Nested tuples are still written as strict arrays.
+	/// With references, each element has its own reference table, as each is
+	/// read as a value of its own.
+	pub fn flatten(mut self, flatten: bool) -> Self {
+		self.flatten = flatten;
+		self
+	}
+
+	/// Write structs as TypedObject, with the Rust struct name as class name.
+	pub fn typed_objects(mut self, typed_objects: bool) -> Self {
+		self.typed_objects = typed_objects;
+		self
+	}
 
+	/// Class names to write instead of the Rust struct names, as
+	/// `(struct name, class name)` pairs. Structs listed here are written as
+	/// TypedObject even when `typed_objects` is off.
+	pub fn class_aliases(mut self, class_aliases: &'a [(&'a str, &'a str)]) -> Self {
+		self.class_aliases = class_aliases;
+		self
+	}
+
+	/// How to write integers beyond 2^53, rounded by default.
+	pub fn integer_policy(mut self, integer_policy: IntegerPolicy) -> Self {
+		self.integer_policy = integer_policy;
+		self
+	}
+
+	/// Leave out struct fields holding `None` instead of writing them as
+	/// Undefined, which reads the same in ActionScript. Fields of a struct
+	/// written as an ECMA array are all kept, their count is written first.
+	pub fn skip_none_fields(mut self, skip_none_fields: bool) -> Self {
+		self.skip_none_fields = skip_none_fields;
+		self
+	}
+
+	/// Write a complex value (object, ECMA array, strict array or typed
+	/// object) identical to one written before as a Reference to it. Each
+	/// nested complex value is encoded twice to be compared, so this is slower.
+	pub fn references(mut self, references: bool) -> Self {
+		self.references = if references { Some(Rc::new(RefCell::new(References::default()))) } else { None };
+		self
+	}
+
+	/// Class name a struct is written with, if it is written as a TypedObject.
+	fn class_name<'b>(&self, name: &'b str) -> Option<&'b str> where 'a: 'b {
+		match self.class_aliases.iter().find(|&&(n, _)| n == name) {
+			Some(&(_, alias)) => Some(alias),
+			None if self.typed_objects => Some(name),
+			None => None,
+		}
+	}
+
+	/// Serializer for a nested value, writing to the same writer.
+	fn child<'b>(&'b mut self) -> Serializer<'b, W> {
+		Serializer{writer: &mut *self.writer, flatten: false, typed_objects: self.typed_objects, class_aliases: self.class_aliases, integer_policy: self.integer_policy, skip_none_fields: self.skip_none_fields, references: self.references.clone(), marker: None}
+	}
+
+	/// Serializer for an element of a tuple. The elements of a flattened
+	/// tuple are separate values to the reader, each with its own reference
+	/// table, so the table starts over for each of them.
+	fn element<'b>(&'b mut self) -> Serializer<'b, W> {
+		if let (true, Some(references)) = (self.flatten, self.references.as_ref()) {
+			*references.borrow_mut() = References::default();
+		}
+		self.child()
+	}
+
+	/// Serializer for a nested value, writing to another writer.
+	fn with_writer<'b, X>(&'b self, writer: &'b mut X) -> Serializer<'b, X> {
+		Serializer{writer, flatten: false, typed_objects: self.typed_objects, class_aliases: self.class_aliases, integer_policy: self.integer_policy, skip_none_fields: self.skip_none_fields, references: self.references.clone(), marker: None}
+	}
+
+	/// Write an integer a Number can't hold exactly, following the integer policy.
+	fn write_large_integer<I: fmt::Display>(self, v: I, rounded: f64) -> Result<(), self::Error> {
+		match self.integer_policy {
+			IntegerPolicy::Lossy => ser::Serializer::serialize_f64(self, rounded),
+			IntegerPolicy::Error => Err(Error::IntegerTooLarge),
+			IntegerPolicy::String => ser::Serializer::serialize_str(self, &v.to_string()),
+		}
+	}
+
+	/// Number the complex value whose marker is about to be written.
+	fn count_reference(&self) {
+		if let Some(ref references) = self.references {
+			references.borrow_mut().count += 1;
+		}
+	}
+
+	/// Write a nested value, or a reference when it is a complex value
+	/// identical to one already written.
+	fn write_nested<T: ?Sized + ser::Serialize>(self, v: &T) -> Result<(), self::Error> {
+		if let Some(references) = self.references.clone() {
+			let mut key = Vec::new();
+			{
+				let mut ser = self.with_writer(&mut key);
+				ser.references = None;
+				v.serialize(ser)?;
+			}
+			if let Some(0x03) | Some(0x08) | Some(0x0A) | Some(0x10) = key.first() {
+				let mut references = references.borrow_mut();
+				if let Some(&index) = references.indices.get(&key) {
+					self.writer.write_all(&[0x07])?;
+					self.writer.write_u16::<BigEndian>(index)?;
+					return result::Result::Ok(());
+				}
+				if references.count <= u16::MAX as u32 {
+					let index = references.count as u16; // Its marker is the next one counted
+					references.indices.insert(key, index);
+				}
+			}
+		}
+		v.serialize(self)
+	}
+}
+
+pub struct SerializeSeq<'a, W: 'a> {
+	ser: Serializer<'a, W>,
+	buffer: Option<Vec<u8>>, // Elements are buffered when the length is not known up front
+	len: u32,
 }
 
-impl ser::SerializeSeq for SerializeSeq {
+impl<'a, W : io::Write> ser::SerializeSeq for SerializeSeq<'a, W> {
 
 	type Ok = ();
 
 	type Error = Error;
 
-	fn serialize_element<T: ?Sized + ser::Serialize>(&mut self, _v : &T) -> result::Result<(), <Self as ser::SerializeSeq>::Error> {
-		Ok(())
+	fn serialize_element<T: ?Sized + ser::Serialize>(&mut self, v : &T) -> result::Result<(), <Self as ser::SerializeSeq>::Error> {
+		match self.buffer {
+			Some(ref mut buffer) => {
+				self.ser.with_writer(buffer).write_nested(v)?;
+				self.len += 1;
+				Ok(())
+			},
+			None => self.ser.child().write_nested(v),
+		}
 	}
 
 	fn end(self) -> result::Result<<Self as ser::SerializeSeq>::Ok, <Self as ser::SerializeSeq>::Error> {
+		if let Some(buffer) = self.buffer {
+			self.ser.writer.write_all(&[0x0A])?;
+			self.ser.writer.write_u32::<BigEndian>(self.len)?;
+			self.ser.writer.write_all(&buffer)?;
+		}
 		Ok(())
 	}
 }
 
-pub struct SerializeTuple {
+pub struct SerializeTuple<'a, W: 'a> {
+	ser: Serializer<'a, W>,
+	index: usize,
 }
 
-impl ser::SerializeTuple for SerializeTuple {
+impl<'a, W : io::Write> ser::SerializeTuple for SerializeTuple<'a, W> {
 
 	type Ok = ();
 
 	type Error = Error;
 
-	fn serialize_element<T: ?Sized + ser::Serialize>(&mut self, _value : &T) -> result::Result<(), <Self as ser::SerializeTuple>::Error> {
-		Ok(())
+	fn serialize_element<T: ?Sized + ser::Serialize>(&mut self, v : &T) -> result::Result<(), <Self as ser::SerializeTuple>::Error> {
+		let marker = self.ser.marker;
+		let index = self.index;
+		self.index += 1;
+		match marker {
+			Some(Marker::TypedObject) => {
+				// A class name then properties, anything else would not be read back
+				match (index, shape(v)) {
+					(0, Shape::String) | (1, Shape::Object) => {},
+					_ => return Err(Error::InvalidTypedObject),
+				}
+				let mut ser = self.ser.child();
+				ser.marker = marker;
+				v.serialize(ser)
+			},
+			Some(Marker::Date) => {
+				let mut ser = self.ser.child();
+				ser.marker = marker; // Date fields are written without marker
+				v.serialize(ser)
+			},
+			_ => self.ser.element().write_nested(v),
+		}
 	}
 
 	fn end(self) -> result::Result<<Self as ser::SerializeTuple>::Ok, <Self as ser::SerializeTuple>::Error> {
@@ -63,18 +277,18 @@ impl ser::SerializeTuple for SerializeTuple {
 	}
 }
 
-pub struct SerializeTupleStruct {
-
+pub struct SerializeTupleStruct<'a, W: 'a> {
+	ser: Serializer<'a, W>,
 }
 
-impl ser::SerializeTupleStruct for SerializeTupleStruct {
+impl<'a, W : io::Write> ser::SerializeTupleStruct for SerializeTupleStruct<'a, W> {
 
 	type Ok = ();
 
 	type Error = Error;
 
-	fn serialize_field<T: ?Sized + ser::Serialize>(&mut self, _v : &T) -> result::Result<(), <Self as ser::SerializeTupleStruct>::Error> {
-		Ok(())
+	fn serialize_field<T: ?Sized + ser::Serialize>(&mut self, v : &T) -> result::Result<(), <Self as ser::SerializeTupleStruct>::Error> {
+		self.ser.element().write_nested(v)
 	}
 
 	fn end(self) -> result::Result<<Self as ser::SerializeTupleStruct>::Ok, <Self as ser::SerializeTupleStruct>::Error> {
@@ -82,27 +296,30 @@ impl ser::SerializeTupleStruct for SerializeTupleStruct {
 	}
 }
 
-pub struct SerializeTupleVariant {
-
+pub struct SerializeTupleVariant<'a, W: 'a> {
+	ser: Serializer<'a, W>,
 }
 
-impl ser::SerializeTupleVariant for SerializeTupleVariant {
+impl<'a, W : io::Write> ser::SerializeTupleVariant for SerializeTupleVariant<'a, W> {
 
 	type Ok = ();
 
 	type Error = Error;
 
-	fn serialize_field<T: ?Sized + ser::Serialize>(&mut self, _v : &T) -> result::Result<(), <Self as ser::SerializeTupleVariant>::Error> {
-		Ok(())
+	fn serialize_field<T: ?Sized + ser::Serialize>(&mut self, v : &T) -> result::Result<(), <Self as ser::SerializeTupleVariant>::Error> {
+		self.ser.child().write_nested(v)
 	}
 
 	fn end(self) -> result::Result<<Self as ser::SerializeTupleVariant>::Ok, <Self as ser::SerializeTupleVariant>::Error> {
+		self.ser.writer.write_all(&[0, 0, 9])?;
 		Ok(())
 	}
 }
 
 pub struct SerializeMap<'a, W: 'a> {
-	ser: Serializer<'a, W>
+	ser: Serializer<'a, W>,
+	buffer: Option<Vec<u8>>, // ECMA array entries are buffered when their count is not known up front
+	len: u32,
 }
 
 impl<'a, W : io::Write> ser::SerializeMap for SerializeMap<'a, W> {
@@ -114,58 +331,92 @@ impl<'a, W : io::Write> ser::SerializeMap for SerializeMap<'a, W> {
 	fn serialize_key<T: ?Sized + ser::Serialize>(&mut self, key : &T) -> result::Result<(), <Self as ser::SerializeMap>::Error> {
 		let mut ser = Vec::with_capacity(128);
 		{
-			let serializer = Serializer {writer: &mut ser };
-			let _ = key.serialize(serializer);
+			let serializer = Serializer::new(&mut ser);
+			key.serialize(serializer)?;
+		}
+		if ser.first() == Some(&0x0C) {
+			return Err(Error::InvalidSize); // Keys are limited to a u16 length
+		}
+		match self.buffer {
+			Some(ref mut buffer) => {
+				buffer.extend_from_slice(&ser[1..]);
+				self.len += 1;
+			},
+			None => {
+				self.ser.writer.write_all(&ser[1..])?; // While writing an object you don't put the marker for the string
+			},
 		}
-		let _ = self.ser.writer.write(&ser[1..]); // While writing an object you don't put the marker for the string
 		result::Result::Ok(())
 	}
 
 	fn serialize_value<T: ?Sized + ser::Serialize>(&mut self, value : &T) -> result::Result<(), <Self as ser::SerializeMap>::Error> {
-		let _ = value.serialize(Serializer{writer : self.ser.writer});
+		match self.buffer {
+			Some(ref mut buffer) => {
+				self.ser.with_writer(buffer).write_nested(value)?;
+			},
+			None => {
+				self.ser.child().write_nested(value)?;
+			},
+		}
 		result::Result::Ok(())
 	}
 
 	fn end(self) -> result::Result<<Self as ser::SerializeMap>::Ok, <Self as ser::SerializeMap>::Error> {
-		let _ = self.ser.writer.write(&[0, 0, 9]);
+		if let Some(buffer) = self.buffer {
+			self.ser.writer.write_all(&[0x08])?;
+			self.ser.writer.write_u32::<BigEndian>(self.len)?;
+			self.ser.writer.write_all(&buffer)?;
+		}
+		self.ser.writer.write_all(&[0, 0, 9])?;
 		result::Result::Ok(())
 	}
 }
 
-pub struct SerializeStruct {
-
+pub struct SerializeStruct<'a, W: 'a> {
+	ser: Serializer<'a, W>,
+	skip_none: bool,
 }
 
-impl ser::SerializeStruct for SerializeStruct {
+impl<'a, W : io::Write> ser::SerializeStruct for SerializeStruct<'a, W> {
 	type Ok = ();
 
 	type Error = Error;
 
-	fn serialize_field<T: ?Sized + ser::Serialize>(&mut self, _key: &'static str, _v : &T) -> result::Result<(), <Self as ser::SerializeStruct>::Error> {
-		Ok(())
+	fn serialize_field<T: ?Sized + ser::Serialize>(&mut self, key: &'static str, v : &T) -> result::Result<(), <Self as ser::SerializeStruct>::Error> {
+		if self.skip_none && is_none(v) {
+			return result::Result::Ok(());
+		}
+		self.ser.write_str(key)?; // Object keys are written without the string marker
+		self.ser.child().write_nested(v)
 	}
 
 	fn end(self) -> result::Result<Self::Ok, Self::Error> {
-		Ok(())
+		self.ser.writer.write_all(&[0, 0, 9])?;
+		result::Result::Ok(())
 	}
 
 }
 
-pub struct SerializeStructVariant {
-
+pub struct SerializeStructVariant<'a, W: 'a> {
+	ser: Serializer<'a, W>,
 }
 
-impl ser::SerializeStructVariant for SerializeStructVariant {
+impl<'a, W : io::Write> ser::SerializeStructVariant for SerializeStructVariant<'a, W> {
 
 	type Ok = ();
 
 	type Error = Error;
 
-	fn serialize_field<T: ?Sized + ser::Serialize>(&mut self, _key: &'static str, _v : &T) -> result::Result<(), <Self as ser::SerializeStructVariant>::Error> {
-		Ok(())
+	fn serialize_field<T: ?Sized + ser::Serialize>(&mut self, key: &'static str, v : &T) -> result::Result<(), <Self as ser::SerializeStructVariant>::Error> {
+		if self.ser.skip_none_fields && is_none(v) {
+			return result::Result::Ok(());
+		}
+		self.ser.write_str(key)?;
+		self.ser.child().write_nested(v)
 	}
 
 	fn end(self) -> result::Result<Self::Ok, Self::Error> {
+		self.ser.writer.write_all(&[0, 0, 9, 0, 0, 9])?; // End of the fields, then end of the wrapping object
 		Ok(())
 	}
 }
@@ -174,11 +425,17 @@ impl ser::SerializeStructVariant for SerializeStructVariant {
 pub enum Error {
 	None,
 	Error,
+	InvalidSize,
+	IntegerTooLarge,
+	InvalidTypedObject, // Its value is not a struct or a map
+	InvalidEcmaArray, // Its value is not a struct or a map
+	Custom(String),
+	IoError(io::Error),
 }
 
 impl ser::Error for Error {
-	fn custom<T: fmt::Display>(_msg: T) -> Self {
-		Error::Error
+	fn custom<T: fmt::Display>(msg: T) -> Self {
+		Error::Custom(msg.to_string())
 	}
 }
 
@@ -187,14 +444,32 @@ impl error::Error for Error {
 		"Error"
 	}
 
-	fn cause(&self) -> Option<&error::Error> {
-		None
+	fn cause(&self) -> Option<&dyn error::Error> {
+		match *self {
+			Error::IoError(ref err) => Some(err),
+			_ => None,
+		}
 	}
 }
 
 impl fmt::Display for Error {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		write!(f, "Error")
+		match *self {
+			Error::None => write!(f, "unknown error"),
+			Error::Error => write!(f, "error"),
+			Error::InvalidSize => write!(f, "string or key too long for its length field"),
+			Error::IntegerTooLarge => write!(f, "integer beyond 2^53, a Number can't hold it exactly"),
+			Error::InvalidTypedObject => write!(f, "a TypedObject must hold a struct or a map"),
+			Error::InvalidEcmaArray => write!(f, "an ECMA array must hold a struct or a map"),
+			Error::Custom(ref msg) => write!(f, "{}", msg),
+			Error::IoError(ref err) => write!(f, "{}", err),
+		}
+	}
+}
+
+impl From<io::Error> for Error {
+	fn from(err: io::Error) -> Error {
+		Error::IoError(err)
 	}
 }
 
@@ -205,27 +480,27 @@ where W: io::Write,
 
 	type Error = Error;
 
-	type SerializeSeq = SerializeSeq;
+	type SerializeSeq = SerializeSeq<'a, W>;
 
-	type SerializeTuple = SerializeTuple;
+	type SerializeTuple = SerializeTuple<'a, W>;
 
-	type SerializeTupleStruct = SerializeTupleStruct;
+	type SerializeTupleStruct = SerializeTupleStruct<'a, W>;
 
-	type SerializeTupleVariant = SerializeTupleVariant;
+	type SerializeTupleVariant = SerializeTupleVariant<'a, W>;
 
 	type SerializeMap = SerializeMap<'a, W>;
 
-	type SerializeStruct = SerializeStruct;
+	type SerializeStruct = SerializeStruct<'a, W>;
 
-	type SerializeStructVariant = SerializeStructVariant;
+	type SerializeStructVariant = SerializeStructVariant<'a, W>;
 
 	fn serialize_bool(self, v: bool) -> Result<(), self::Error> {
-		let _ = self.writer.write(&[0x01]);
+		self.writer.write_all(&[0x01])?;
 		if v {
-			let _ = self.writer.write(&[1]);
+			self.writer.write_all(&[1])?;
 		}
 		else {
-			let _ = self.writer.write(&[0]);
+			self.writer.write_all(&[0])?;
 		}
 		result::Result::Ok(())
 	}
@@ -235,15 +510,24 @@ where W: io::Write,
 	}
 
 	fn serialize_i8(self, v: i8) -> Result<(), self::Error> {
-		self.serialize_u64(v as u64)
+		self.serialize_i64(v as i64)
 	}
 
 	fn serialize_u16(self, v: u16) -> Result<(), self::Error> {
+		if let Some(Marker::Reference) = self.marker {
+			self.writer.write_all(&[0x07])?;
+			self.writer.write_u16::<BigEndian>(v)?;
+			return result::Result::Ok(());
+		}
 		self.serialize_u64(v as u64)
 	}
 
 	fn serialize_i16(self, v: i16) -> Result<(), self::Error> {
-		self.serialize_u64(v as u64)
+		if let Some(Marker::Date) = self.marker {
+			self.writer.write_i16::<BigEndian>(v)?; // Time zone of a Date
+			return result::Result::Ok(());
+		}
+		self.serialize_i64(v as i64)
 	}
 
 	fn serialize_u32(self, v: u32) -> Result<(), self::Error> {
@@ -251,15 +535,21 @@ where W: io::Write,
 	}
 
 	fn serialize_i32(self, v: i32) -> Result<(), self::Error> {
-		self.serialize_u64(v as u64)
+		self.serialize_i64(v as i64)
 	}
 
 	fn serialize_u64(self, v: u64) -> Result<(), self::Error> {
+		if v > MAX_SAFE_INTEGER {
+			return self.write_large_integer(v, v as f64);
+		}
 		self.serialize_f64(v as f64)
 	}
 
 	fn serialize_i64(self, v: i64) -> Result<(), self::Error> {
-		self.serialize_u64(v as u64)
+		if v.unsigned_abs() > MAX_SAFE_INTEGER {
+			return self.write_large_integer(v, v as f64);
+		}
+		self.serialize_f64(v as f64)
 	}
 
 	fn serialize_f32(self, v: f32) -> Result<(), self::Error> {
@@ -267,26 +557,37 @@ where W: io::Write,
 	}
 
 	fn serialize_f64(self, v: f64) -> Result<(), self::Error> {
-		let _ = self.writer.write(&[0x00]);
-		let mut wtr = vec![];
-		wtr.write_f64::<BigEndian>(v).unwrap();
-		for digit in &mut wtr {
-			let _ = self.writer.write(&[*digit]);
+		if let Some(Marker::Date) = self.marker {
+			self.writer.write_f64::<BigEndian>(v)?; // Milliseconds of a Date
+			return result::Result::Ok(());
 		}
+		self.writer.write_all(&[0x00])?;
+		self.writer.write_f64::<BigEndian>(v)?;
 		result::Result::Ok(())
 	}
 
-	fn serialize_bytes(self, _value: &[u8]) -> Result<(), Self::Error>{
-		result::Result::Ok(())
+	/// AMF0 has no byte array type, bytes are written as a strict array of
+	/// Numbers, one per byte.
+	fn serialize_bytes(self, value: &[u8]) -> Result<(), Self::Error>{
+		use serde::ser::SerializeSeq;
+		let mut seq = self.serialize_seq(Some(value.len()))?;
+		for byte in value {
+			seq.serialize_element(byte)?;
+		}
+		seq.end()
 	}
 
 	fn serialize_unit(self) -> Result<(), self::Error> {
-		let _ = self.writer.write(&[0x05]);
+		if let Some(Marker::Undefined) = self.marker {
+			self.writer.write_all(&[0x06])?;
+		} else {
+			self.writer.write_all(&[0x05])?;
+		}
 		result::Result::Ok(())
 	}
 
 	fn serialize_none(self) -> Result<(), self::Error> {
-		let _ = self.writer.write(&[0x06]);
+		self.writer.write_all(&[0x06])?;
 		result::Result::Ok(())
 	}
 
@@ -298,82 +599,306 @@ where W: io::Write,
 		self.serialize_unit()
 	}
 
-	fn serialize_unit_variant(self, _name: &'static str, _variant_index: usize, _variant: &'static str) -> Result<(), self::Error> {
-		self.serialize_unit()
+	/// Unit variants are written as a string holding the variant name.
+	fn serialize_unit_variant(self, _name: &'static str, _variant_index: usize, variant: &'static str) -> Result<(), self::Error> {
+		self.serialize_str(variant)
 	}
 
+	/// A char is written as a String holding its UTF-8 encoding.
 	fn serialize_char(self, v: char) -> Result<(), self::Error> 
 	{
-		let _ = self.writer.write(&[0x02]);
-		let _ = self.writer.write(&[0, 1]);
-		let _ = write!(self.writer, "{:?}", v);
-		result::Result::Ok(())
+		let mut buffer = [0; 4];
+		self.serialize_str(v.encode_utf8(&mut buffer))
 	}
 
-	fn serialize_str(self, v: &str) -> Result<(), self::Error> 
+	/// Strings over 65535 bytes are written as LongString.
+	fn serialize_str(mut self, v: &str) -> Result<(), self::Error> 
 	{
-		let _ = self.writer.write(&[0x02]);
-		self.write_str(v);
-		result::Result::Ok(())
+		match self.marker {
+			Some(Marker::LongString) => {
+				self.writer.write_all(&[0x0C])?;
+				self.write_long_str(v)
+			},
+			Some(Marker::XMLDocument) => {
+				self.writer.write_all(&[0x0F])?;
+				self.write_long_str(v)
+			},
+			Some(Marker::TypedObject) => self.write_str(v), // Class name of a TypedObject
+			_ if v.len() > u16::MAX as usize => {
+				self.writer.write_all(&[0x0C])?;
+				self.write_long_str(v)
+			},
+			_ => {
+				self.writer.write_all(&[0x02])?;
+				self.write_str(v)
+			},
+		}
 	}
 
 
-	fn serialize_newtype_struct<T: ser::Serialize + ?Sized>(self, _name: &'static str, _value: &T) -> Result<(), Self::Error> {
-		// TODO
-		result::Result::Ok(())
+	/// Newtype structs are transparent, only the inner value is written.
+	fn serialize_newtype_struct<T: ser::Serialize + ?Sized>(mut self, name: &'static str, value: &T) -> Result<(), Self::Error> {
+		self.marker = match name {
+			LONG_STRING => Some(Marker::LongString),
+			DATE => Some(Marker::Date),
+			XML_DOCUMENT => Some(Marker::XMLDocument),
+			ECMA_ARRAY => Some(Marker::ECMAArray),
+			TYPED_OBJECT => Some(Marker::TypedObject),
+			UNDEFINED => Some(Marker::Undefined),
+			REFERENCE => Some(Marker::Reference),
+			_ => self.marker,
+		};
+		if let Some(Marker::ECMAArray) = self.marker {
+			if shape(value) != Shape::Object {
+				return Err(Error::InvalidEcmaArray); // Anything else would be written without its marker
+			}
+		}
+		value.serialize(self)
 	}
 
-	fn serialize_newtype_variant<T: ser::Serialize + ?Sized>(self, _name: &'static str, _variant_index: usize, _variant: &'static str, _value: &T) -> Result<(), Self::Error> {
-		// TODO
+	/// Other variants are externally tagged: an object with a single key, the
+	/// variant name, whose value is the variant content.
+	fn serialize_newtype_variant<T: ser::Serialize + ?Sized>(mut self, _name: &'static str, _variant_index: usize, variant: &'static str, value: &T) -> Result<(), Self::Error> {
+		self.write_variant_key(variant)?;
+		self.child().write_nested(value)?;
+		self.writer.write_all(&[0, 0, 9])?;
 		result::Result::Ok(())
 	}
 
-	fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
-		// TODO
-		result::Result::Ok(SerializeSeq{})
+	fn serialize_seq(mut self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+		match len {
+			Some(n) => {
+				self.write_strict_array_header(n as u32)?;
+				result::Result::Ok(SerializeSeq{ser: self, buffer: None, len: n as u32})
+			},
+			None => {
+				self.count_reference(); // The strict array header is written once the elements are
+				result::Result::Ok(SerializeSeq{ser: self, buffer: Some(Vec::new()), len: 0})
+			},
+		}
 	}
 
 	fn serialize_seq_fixed_size(self, size: usize) -> Result<Self::SerializeSeq, Self::Error> {
 		self.serialize_seq(Some(size))
 	}
 
-	fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
-		result::Result::Ok(SerializeTuple{})
+	fn serialize_tuple(mut self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+		if let Some(Marker::Date) = self.marker {
+			self.writer.write_all(&[0x0B])?;
+		} else if let Some(Marker::TypedObject) = self.marker {
+			self.count_reference();
+			self.writer.write_all(&[0x10])?;
+		} else if !self.flatten {
+			self.write_strict_array_header(len as u32)?;
+		}
+		result::Result::Ok(SerializeTuple{ser: self, index: 0})
 	}
 
-	fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, Self::Error> {
-		// TODO
-		result::Result::Ok(SerializeTupleStruct{})
+	fn serialize_tuple_struct(mut self, _name: &'static str, len: usize) -> Result<Self::SerializeTupleStruct, Self::Error> {
+		if !self.flatten {
+			self.write_strict_array_header(len as u32)?;
+		}
+		result::Result::Ok(SerializeTupleStruct{ser: self})
 	}
 
-	fn serialize_tuple_variant(self, _name: &'static str, _variant_index: usize, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant, Self::Error> {
-		// TODO
-		result::Result::Ok(SerializeTupleVariant{})
+	fn serialize_tuple_variant(mut self, _name: &'static str, _variant_index: usize, variant: &'static str, len: usize) -> Result<Self::SerializeTupleVariant, Self::Error> {
+		self.write_variant_key(variant)?;
+		self.write_strict_array_header(len as u32)?;
+		result::Result::Ok(SerializeTupleVariant{ser: self})
 	}
 
 	fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-		match len {
-			Some(n) => {
-				let _ = self.writer.write(&[0x08]);
-				let mut wtr = vec![];
-				wtr.write_u32::<BigEndian>(n as u32).unwrap();
-				for digit in &mut wtr {
-					let _ = self.writer.write(&[*digit]);
-				}
+		match (self.marker, len) {
+			(Some(Marker::TypedObject), _) => {}, // Properties of a TypedObject follow its class name
+			(Some(Marker::ECMAArray), None) => {
+				self.count_reference();
+				return result::Result::Ok(SerializeMap{ser: self, buffer: Some(Vec::new()), len: 0});
 			},
-			None => {
-				let _ = self.writer.write(&[0x03]);
+			(_, Some(n)) => {
+				self.count_reference();
+				self.writer.write_all(&[0x08])?;
+				self.writer.write_u32::<BigEndian>(n as u32)?;
+			},
+			(_, None) => {
+				self.count_reference();
+				self.writer.write_all(&[0x03])?;
 			}
 		};
-		result::Result::Ok(SerializeMap{ser: self})
+		result::Result::Ok(SerializeMap{ser: self, buffer: None, len: 0})
+	}
+
+	/// Structs are written as Object, or as TypedObject when the serializer
+	/// is configured to give them a class name.
+	fn serialize_struct(mut self, name: &'static str, len: usize) -> Result<Self::SerializeStruct, Self::Error> {
+		let class_name = self.class_name(name);
+		let skip_none = self.skip_none_fields && !matches!(self.marker, Some(Marker::ECMAArray));
+		match (self.marker, class_name) {
+			(Some(Marker::TypedObject), _) => {}, // Properties of a TypedObject follow its class name
+			(Some(Marker::ECMAArray), _) => {
+				self.count_reference();
+				self.writer.write_all(&[0x08])?;
+				self.writer.write_u32::<BigEndian>(len as u32)?;
+			},
+			(_, Some(class_name)) => {
+				self.count_reference();
+				self.writer.write_all(&[0x10])?;
+				self.write_str(class_name)?;
+			},
+			_ => {
+				self.count_reference();
+				self.writer.write_all(&[0x03])?;
+			},
+		}
+		result::Result::Ok(SerializeStruct{ser: self, skip_none})
 	}
 
-	fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, Self::Error> {
-		result::Result::Ok(SerializeStruct{})
+	fn serialize_struct_variant(mut self, _name: &'static str, _variant_index: usize, variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant, Self::Error> {
+		self.write_variant_key(variant)?;
+		self.count_reference();
+		self.writer.write_all(&[0x03])?;
+		result::Result::Ok(SerializeStructVariant{ser: self})
 	}
+}
+
+/// What a value serializes as, found without writing it.
+#[derive(Debug, PartialEq)]
+enum Shape {
+	None,
+	String,
+	Object,
+	Other,
+}
+
+fn shape<T: ?Sized + ser::Serialize>(v: &T) -> Shape {
+	v.serialize(ShapeProbe).unwrap_or(Shape::Other)
+}
+
+/// Whether a value serializes as `None`.
+fn is_none<T: ?Sized + ser::Serialize>(v: &T) -> bool {
+	shape(v) == Shape::None
+}
+
+/// Serializer answering the `Shape` of a value, without writing it.
+struct ShapeProbe;
 
-	fn serialize_struct_variant(self, _name: &'static str, _variant_index: usize, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant, Self::Error> {
-		// TODO
-		result::Result::Ok(SerializeStructVariant{})
+impl ser::Serializer for ShapeProbe {
+	type Ok = Shape;
+
+	type Error = Error;
+
+	type SerializeSeq = ser::Impossible<Shape, Error>;
+
+	type SerializeTuple = ser::Impossible<Shape, Error>;
+
+	type SerializeTupleStruct = ser::Impossible<Shape, Error>;
+
+	type SerializeTupleVariant = ser::Impossible<Shape, Error>;
+
+	type SerializeMap = ObjectProbe;
+
+	type SerializeStruct = ObjectProbe;
+
+	type SerializeStructVariant = ser::Impossible<Shape, Error>;
+
+	fn serialize_none(self) -> Result<Shape, Error> {
+		Ok(Shape::None)
+	}
+
+	fn serialize_str(self, _v: &str) -> Result<Shape, Error> {
+		Ok(Shape::String)
+	}
+
+	fn serialize_bool(self, _v: bool) -> Result<Shape, Error> { Ok(Shape::Other) }
+	fn serialize_i8(self, _v: i8) -> Result<Shape, Error> { Ok(Shape::Other) }
+	fn serialize_i16(self, _v: i16) -> Result<Shape, Error> { Ok(Shape::Other) }
+	fn serialize_i32(self, _v: i32) -> Result<Shape, Error> { Ok(Shape::Other) }
+	fn serialize_i64(self, _v: i64) -> Result<Shape, Error> { Ok(Shape::Other) }
+	fn serialize_u8(self, _v: u8) -> Result<Shape, Error> { Ok(Shape::Other) }
+	fn serialize_u16(self, _v: u16) -> Result<Shape, Error> { Ok(Shape::Other) }
+	fn serialize_u32(self, _v: u32) -> Result<Shape, Error> { Ok(Shape::Other) }
+	fn serialize_u64(self, _v: u64) -> Result<Shape, Error> { Ok(Shape::Other) }
+	fn serialize_f32(self, _v: f32) -> Result<Shape, Error> { Ok(Shape::Other) }
+	fn serialize_f64(self, _v: f64) -> Result<Shape, Error> { Ok(Shape::Other) }
+	fn serialize_char(self, _v: char) -> Result<Shape, Error> { Ok(Shape::Other) }
+	fn serialize_bytes(self, _v: &[u8]) -> Result<Shape, Error> { Ok(Shape::Other) }
+	fn serialize_unit(self) -> Result<Shape, Error> { Ok(Shape::Other) }
+	fn serialize_unit_struct(self, _name: &'static str) -> Result<Shape, Error> { Ok(Shape::Other) }
+
+	/// `Some` is written as its content, which is not `None` though.
+	fn serialize_some<T: ?Sized + ser::Serialize>(self, v: &T) -> Result<Shape, Error> {
+		match v.serialize(self)? {
+			Shape::None => Ok(Shape::Other),
+			shape => Ok(shape),
+		}
+	}
+
+	fn serialize_unit_variant(self, _name: &'static str, _variant_index: usize, _variant: &'static str) -> Result<Shape, Error> {
+		Ok(Shape::Other)
+	}
+
+	/// Newtype structs are transparent, except the ones choosing a marker.
+	fn serialize_newtype_struct<T: ?Sized + ser::Serialize>(self, name: &'static str, v: &T) -> Result<Shape, Error> {
+		match name {
+			LONG_STRING | DATE | XML_DOCUMENT | ECMA_ARRAY | TYPED_OBJECT | UNDEFINED | REFERENCE => Ok(Shape::Other),
+			_ => v.serialize(self),
+		}
+	}
+
+	fn serialize_newtype_variant<T: ?Sized + ser::Serialize>(self, _name: &'static str, _variant_index: usize, _variant: &'static str, _v: &T) -> Result<Shape, Error> {
+		Ok(Shape::Other)
 	}
+
+	// Other compound values are not needed, the probe stops there
+	fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> { Err(Error::None) }
+	fn serialize_seq_fixed_size(self, _size: usize) -> Result<Self::SerializeSeq, Error> { Err(Error::None) }
+	fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> { Err(Error::None) }
+
+	fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, Error> {
+		Err(Error::None)
+	}
+
+	fn serialize_tuple_variant(self, _name: &'static str, _variant_index: usize, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant, Error> {
+		Err(Error::None)
+	}
+
+	fn serialize_map(self, _len: Option<usize>) -> Result<ObjectProbe, Error> { Ok(ObjectProbe) }
+	fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<ObjectProbe, Error> { Ok(ObjectProbe) }
+
+	fn serialize_struct_variant(self, _name: &'static str, _variant_index: usize, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant, Error> {
+		Err(Error::None)
+	}
+}
+
+/// Entries of a map or struct seen by `ShapeProbe`, skipped unread.
+struct ObjectProbe;
+
+impl ser::SerializeMap for ObjectProbe {
+	type Ok = Shape;
+
+	type Error = Error;
+
+	fn serialize_key<T: ?Sized + ser::Serialize>(&mut self, _key: &T) -> Result<(), Error> { Ok(()) }
+	fn serialize_value<T: ?Sized + ser::Serialize>(&mut self, _value: &T) -> Result<(), Error> { Ok(()) }
+	fn end(self) -> Result<Shape, Error> { Ok(Shape::Object) }
+}
+
+impl ser::SerializeStruct for ObjectProbe {
+	type Ok = Shape;
+
+	type Error = Error;
+
+	fn serialize_field<T: ?Sized + ser::Serialize>(&mut self, _key: &'static str, _v: &T) -> Result<(), Error> { Ok(()) }
+	fn end(self) -> Result<Shape, Error> { Ok(Shape::Object) }
+}
+
+/// Serialize a value into a new buffer.
+pub fn to_vec<T: ?Sized + ser::Serialize>(value: &T) -> Result<Vec<u8>, self::Error> {
+	let mut writer = Vec::with_capacity(128);
+	to_writer(&mut writer, value)?;
+	Ok(writer)
+}
+
+/// Serialize a value into a writer.
+pub fn to_writer<W: io::Write, T: ?Sized + ser::Serialize>(mut writer: W, value: &T) -> Result<(), self::Error> {
+	value.serialize(Serializer::new(&mut writer))
 }
@@ -0,0 +1,288 @@
+//! Wrapper types choosing the AMF marker of a value.
+//!
+//! The serde data model has no place for some AMF types. Wrapping a field in
+//! one of these types makes `Serializer` write it with the matching marker,
+//! and makes `Deserializer` read it back, without going through `Value`.
+//! With other serde formats they behave like the value they wrap.
+
+use std::fmt;
+use std::marker::PhantomData;
+use serde;
+use serde::de;
+use value::{ECMA_ARRAY, LONG_STRING, TYPED_OBJECT, UNDEFINED, XML_DOCUMENT};
+
+pub use value::Date;
+
+/// String written with the LongString (0x0C) marker, whatever its length.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LongString(pub String);
+
+impl serde::Serialize for LongString {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+        serializer.serialize_newtype_struct(LONG_STRING, &self.0)
+    }
+}
+
+impl de::Deserialize for LongString {
+    fn deserialize<D>(deserializer: D) -> Result<LongString, D::Error>
+        where D: de::Deserializer
+    {
+        struct LongStringVisitor;
+
+        impl de::Visitor for LongStringVisitor {
+            type Value = LongString;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                fmt.write_str("AMF long string")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<LongString, E>
+                where E: de::Error,
+            {
+                Ok(LongString(value.to_string()))
+            }
+
+            fn visit_string<E>(self, value: String) -> Result<LongString, E>
+                where E: de::Error,
+            {
+                Ok(LongString(value))
+            }
+
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<LongString, D::Error>
+                where D: de::Deserializer
+            {
+                <String as de::Deserialize>::deserialize(deserializer).map(LongString)
+            }
+
+            fn visit_enum<V>(self, visitor: V) -> Result<LongString, V::Error>
+                where V: de::EnumVisitor
+            {
+                use serde::de::VariantVisitor;
+
+                let (tag, content) = visitor.visit_variant::<String>()?;
+                match tag.as_str() {
+                    "LongString" => content.visit_newtype().map(LongString),
+                    _ => Err(de::Error::invalid_value(de::Unexpected::Str(&tag), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(LONG_STRING, LongStringVisitor)
+    }
+}
+
+/// XML document, written with the XMLDocument (0x0F) marker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlDocument(pub String);
+
+impl serde::Serialize for XmlDocument {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+        serializer.serialize_newtype_struct(XML_DOCUMENT, &self.0)
+    }
+}
+
+impl de::Deserialize for XmlDocument {
+    fn deserialize<D>(deserializer: D) -> Result<XmlDocument, D::Error>
+        where D: de::Deserializer
+    {
+        struct XmlDocumentVisitor;
+
+        impl de::Visitor for XmlDocumentVisitor {
+            type Value = XmlDocument;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                fmt.write_str("AMF XML document")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<XmlDocument, E>
+                where E: de::Error,
+            {
+                Ok(XmlDocument(value.to_string()))
+            }
+
+            fn visit_string<E>(self, value: String) -> Result<XmlDocument, E>
+                where E: de::Error,
+            {
+                Ok(XmlDocument(value))
+            }
+
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<XmlDocument, D::Error>
+                where D: de::Deserializer
+            {
+                <String as de::Deserialize>::deserialize(deserializer).map(XmlDocument)
+            }
+
+            fn visit_enum<V>(self, visitor: V) -> Result<XmlDocument, V::Error>
+                where V: de::EnumVisitor
+            {
+                use serde::de::VariantVisitor;
+
+                let (tag, content) = visitor.visit_variant::<String>()?;
+                match tag.as_str() {
+                    "XMLDocument" | "LongString" => content.visit_newtype().map(XmlDocument),
+                    _ => Err(de::Error::invalid_value(de::Unexpected::Str(&tag), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(XML_DOCUMENT, XmlDocumentVisitor)
+    }
+}
+
+/// ECMA array, the wrapped map or struct is written with the ECMAArray
+/// (0x08) marker instead of the Object (0x03) one, serializing anything else
+/// fails. Both are accepted when deserializing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EcmaArray<T>(pub T);
+
+impl<T: serde::Serialize> serde::Serialize for EcmaArray<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+        serializer.serialize_newtype_struct(ECMA_ARRAY, &self.0)
+    }
+}
+
+impl<T: de::Deserialize> de::Deserialize for EcmaArray<T> {
+    fn deserialize<D>(deserializer: D) -> Result<EcmaArray<T>, D::Error>
+        where D: de::Deserializer
+    {
+        struct EcmaArrayVisitor<T>(PhantomData<T>);
+
+        impl<T: de::Deserialize> de::Visitor for EcmaArrayVisitor<T> {
+            type Value = EcmaArray<T>;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                fmt.write_str("AMF ECMA array")
+            }
+
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<EcmaArray<T>, D::Error>
+                where D: de::Deserializer
+            {
+                <T as de::Deserialize>::deserialize(deserializer).map(EcmaArray)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(ECMA_ARRAY, EcmaArrayVisitor(PhantomData))
+    }
+}
+
+/// Object with a class name, written with the TypedObject (0x10) marker.
+/// The value must serialize as a struct or a map, serializing anything else
+/// fails. A plain object is accepted when deserializing, with an empty class
+/// name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypedObject<T> {
+    pub class_name: String,
+    pub value: T,
+}
+
+impl<T> TypedObject<T> {
+    pub fn new<S: Into<String>>(class_name: S, value: T) -> Self {
+        TypedObject {
+            class_name: class_name.into(),
+            value,
+        }
+    }
+}
+
+impl<T: serde::Serialize> serde::Serialize for TypedObject<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+        serializer.serialize_newtype_struct(TYPED_OBJECT, &(&self.class_name, &self.value))
+    }
+}
+
+impl<T: de::Deserialize> de::Deserialize for TypedObject<T> {
+    fn deserialize<D>(deserializer: D) -> Result<TypedObject<T>, D::Error>
+        where D: de::Deserializer
+    {
+        struct TypedObjectVisitor<T>(PhantomData<T>);
+
+        impl<T: de::Deserialize> de::Visitor for TypedObjectVisitor<T> {
+            type Value = TypedObject<T>;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                fmt.write_str("AMF typed object")
+            }
+
+            fn visit_seq<V>(self, mut visitor: V) -> Result<TypedObject<T>, V::Error>
+                where V: de::SeqVisitor
+            {
+                let class_name = visitor.visit()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let value = visitor.visit()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                Ok(TypedObject::new::<String>(class_name, value))
+            }
+
+            fn visit_map<V>(self, visitor: V) -> Result<TypedObject<T>, V::Error>
+                where V: de::MapVisitor
+            {
+                let value = <T as de::Deserialize>::deserialize(de::value::MapVisitorDeserializer::new(visitor))?;
+                Ok(TypedObject::new("", value))
+            }
+
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<TypedObject<T>, D::Error>
+                where D: de::Deserializer
+            {
+                deserializer.deserialize_tuple(2, self)
+            }
+
+            fn visit_enum<V>(self, visitor: V) -> Result<TypedObject<T>, V::Error>
+                where V: de::EnumVisitor
+            {
+                use serde::de::VariantVisitor;
+
+                let (tag, content) = visitor.visit_variant::<String>()?;
+                match tag.as_str() {
+                    "TypedObject" => content.visit_tuple(2, self),
+                    _ => Err(de::Error::invalid_value(de::Unexpected::Str(&tag), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(TYPED_OBJECT, TypedObjectVisitor(PhantomData))
+    }
+}
+
+/// AMF undefined, written with the Undefined (0x06) marker.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Undefined;
+
+impl serde::Serialize for Undefined {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+        serializer.serialize_newtype_struct(UNDEFINED, &())
+    }
+}
+
+impl de::Deserialize for Undefined {
+    fn deserialize<D>(deserializer: D) -> Result<Undefined, D::Error>
+        where D: de::Deserializer
+    {
+        struct UndefinedVisitor;
+
+        impl de::Visitor for UndefinedVisitor {
+            type Value = Undefined;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                fmt.write_str("AMF undefined")
+            }
+
+            fn visit_none<E>(self) -> Result<Undefined, E>
+                where E: de::Error,
+            {
+                Ok(Undefined)
+            }
+
+            fn visit_unit<E>(self) -> Result<Undefined, E>
+                where E: de::Error,
+            {
+                Ok(Undefined)
+            }
+
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Undefined, D::Error>
+                where D: de::Deserializer
+            {
+                <() as de::Deserialize>::deserialize(deserializer).map(|_| Undefined)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(UNDEFINED, UndefinedVisitor)
+    }
+}
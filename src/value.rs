@@ -2,19 +2,309 @@ extern crate serde;
 extern crate byteorder;
 
 use std::collections::BTreeMap;
+use std::error;
 use std::fmt;
-use std::str;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use serde::de;
-use std::convert::From;
+use std::convert::TryFrom;
 
 pub type Map<K, V> = BTreeMap<K, V>;
 
+/// Newtype struct name the serializer recognises to write a LongString (0x0C)
+/// whatever the length of the string.
+#[doc(hidden)]
+pub const LONG_STRING: &str = "$__amf_LongString";
+
+/// Newtype struct name `Value` deserializes through, so the deserializer
+/// reports the AMF types serde has no equivalent for.
+#[doc(hidden)]
+pub const VALUE: &str = "$__amf_Value";
+
+/// Newtype struct name `Date` serializes through, so the serializer writes
+/// it with the Date (0x0B) marker.
+#[doc(hidden)]
+pub const DATE: &str = "$__amf_Date";
+
+/// Newtype struct names of the other AMF types the serializer and the
+/// deserializer recognise, see the `types` module.
+#[doc(hidden)]
+pub const XML_DOCUMENT: &str = "$__amf_XMLDocument";
+#[doc(hidden)]
+pub const ECMA_ARRAY: &str = "$__amf_ECMAArray";
+#[doc(hidden)]
+pub const TYPED_OBJECT: &str = "$__amf_TypedObject";
+#[doc(hidden)]
+pub const UNDEFINED: &str = "$__amf_Undefined";
+#[doc(hidden)]
+pub const REFERENCE: &str = "$__amf_Reference";
+
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+// ECMAScript dates are limited to 100 000 000 days around the epoch.
+const MAX_MILLIS: f64 = 8.64e15;
+
+/// AMF Date: milliseconds since the Unix epoch, in UTC, and a time zone
+/// offset in minutes east of UTC.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Date {
     date: f64,
     time_zone: i16,
 }
 
+impl Date {
+
+    /// Returns the `Date` at `millis` milliseconds since the Unix epoch, in UTC.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amf::value::Date;
+    ///
+    /// assert_eq!(Date::from_millis(0.).to_string(), "1970-01-01T00:00:00.000Z");
+    /// ```
+    pub fn from_millis(millis: f64) -> Date {
+        Date::with_time_zone(millis, 0)
+    }
+
+    /// Returns the `Date` at `millis` milliseconds since the Unix epoch,
+    /// observed from a time zone `time_zone` minutes east of UTC.
+    pub fn with_time_zone(millis: f64, time_zone: i16) -> Date {
+        Date {
+            date: millis,
+            time_zone,
+        }
+    }
+
+    /// Returns the current `Date`, in UTC.
+    pub fn now() -> Date {
+        Date::from(SystemTime::now())
+    }
+
+    /// Returns the milliseconds since the Unix epoch, in UTC.
+    pub fn millis(&self) -> f64 {
+        self.date
+    }
+
+    /// Returns the time zone offset in minutes east of UTC.
+    pub fn time_zone(&self) -> i16 {
+        self.time_zone
+    }
+
+    /// Returns the `SystemTime` of the `Date`, or None if it is not a
+    /// finite number of milliseconds.
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        if !self.date.is_finite() {
+            return None;
+        }
+        let duration = Duration::from_millis(self.date.abs() as u64);
+        if self.date < 0. {
+            UNIX_EPOCH.checked_sub(duration)
+        } else {
+            UNIX_EPOCH.checked_add(duration)
+        }
+    }
+}
+
+impl From<SystemTime> for Date {
+    fn from(time: SystemTime) -> Date {
+        let millis = match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as f64 * 1000. + d.subsec_millis() as f64,
+            Err(e) => {
+                let d = e.duration();
+                -(d.as_secs() as f64 * 1000. + d.subsec_millis() as f64)
+            },
+        };
+        Date::from_millis(millis)
+    }
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date, and back.
+// See http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (if month <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, month, day)
+}
+
+/// Formats the `Date` as ISO 8601, in the local time of its time zone.
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.date.is_finite() || self.date.abs() > MAX_MILLIS {
+            return write!(f, "Invalid Date");
+        }
+        let local = match (self.date.floor() as i64).checked_add(self.time_zone as i64 * 60_000) {
+            Some(local) => local,
+            None => return write!(f, "Invalid Date"),
+        };
+        let (year, month, day) = civil_from_days(local.div_euclid(MILLIS_PER_DAY));
+        let millis = local.rem_euclid(MILLIS_PER_DAY);
+        if (0..10_000).contains(&year) {
+            write!(f, "{:04}", year)?;
+        } else {
+            write!(f, "{:+07}", year)?;
+        }
+        write!(f, "-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}", month, day,
+            millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000)?;
+        match self.time_zone as i32 {
+            0 => write!(f, "Z"),
+            tz => write!(f, "{}{:02}:{:02}", if tz < 0 { '-' } else { '+' }, tz.abs() / 60, tz.abs() % 60),
+        }
+    }
+}
+
+/// Error returned when parsing a `Date` from a string fails.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseDateError;
+
+impl error::Error for ParseDateError {
+    fn description(&self) -> &str {
+        "invalid ISO 8601 date"
+    }
+}
+
+impl fmt::Display for ParseDateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid ISO 8601 date")
+    }
+}
+
+/// Parses an ISO 8601 date and time like `2017-03-01T12:30:00.250+01:00`.
+/// Fractional seconds are optional, a missing offset means UTC.
+impl FromStr for Date {
+    type Err = ParseDateError;
+
+    fn from_str(s: &str) -> Result<Date, ParseDateError> {
+        fn number(s: &str, range: ::std::ops::Range<usize>) -> Result<i64, ParseDateError> {
+            let digits = s.get(range).ok_or(ParseDateError)?;
+            if !digits.bytes().all(|c| c.is_ascii_digit()) {
+                return Err(ParseDateError);
+            }
+            digits.parse().map_err(|_| ParseDateError)
+        }
+
+        let b = s.as_bytes();
+        if b.len() < 19 || b[4] != b'-' || b[7] != b'-' || (b[10] != b'T' && b[10] != b't') || b[13] != b':' || b[16] != b':' {
+            return Err(ParseDateError);
+        }
+        let (year, month, day) = (number(s, 0..4)?, number(s, 5..7)?, number(s, 8..10)?);
+        let (hour, minute, second) = (number(s, 11..13)?, number(s, 14..16)?, number(s, 17..19)?);
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+            return Err(ParseDateError);
+        }
+
+        let mut i = 19;
+        let mut millis = 0.;
+        if b.get(i) == Some(&b'.') {
+            let start = i + 1;
+            i = start;
+            while b.get(i).is_some_and(|c| c.is_ascii_digit()) {
+                i += 1;
+            }
+            if i == start {
+                return Err(ParseDateError);
+            }
+            millis = format!("0.{}", &s[start..i]).parse::<f64>().map_err(|_| ParseDateError)? * 1000.;
+        }
+
+        let time_zone = match b.get(i) {
+            None => 0,
+            Some(&b'Z') | Some(&b'z') if b.len() == i + 1 => 0,
+            Some(&c) if c == b'+' || c == b'-' => {
+                let (hours, minutes) = match b.len() - i {
+                    6 if b[i + 3] == b':' => (number(s, i + 1..i + 3)?, number(s, i + 4..i + 6)?),
+                    5 => (number(s, i + 1..i + 3)?, number(s, i + 3..i + 5)?),
+                    3 => (number(s, i + 1..i + 3)?, 0),
+                    _ => return Err(ParseDateError),
+                };
+                if minutes > 59 {
+                    return Err(ParseDateError);
+                }
+                let offset = hours * 60 + minutes;
+                if c == b'-' { -offset } else { offset }
+            },
+            _ => return Err(ParseDateError),
+        };
+
+        let local = days_from_civil(year, month, day) * MILLIS_PER_DAY
+            + ((hour * 60 + minute) * 60 + second) * 1000;
+        let utc = local - time_zone * 60_000;
+        Ok(Date::with_time_zone(utc as f64 + millis, time_zone as i16))
+    }
+}
+
+impl serde::Serialize for Date {
+    fn serialize<S>(&self, serializer: S) -> Result<<S as serde::Serializer>::Ok, S::Error> where S: serde::Serializer {
+        serializer.serialize_newtype_struct(DATE, &(self.date, self.time_zone))
+    }
+}
+
+impl de::Deserialize for Date {
+    fn deserialize<D>(deserializer: D) -> Result<Date, D::Error>
+        where D: de::Deserializer
+    {
+        struct DateVisitor;
+
+        impl de::Visitor for DateVisitor {
+            type Value = Date;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error>
+            {
+                fmt.write_str("AMF date")
+            }
+
+            fn visit_f64<E>(self, value: f64) -> Result<Date, E>
+                where E: de::Error,
+            {
+                Ok(Date::from_millis(value))
+            }
+
+            fn visit_seq<V>(self, mut visitor: V) -> Result<Date, V::Error>
+                where V : de::SeqVisitor
+            {
+                let date = visitor.visit()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let time_zone = visitor.visit()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                Ok(Date::with_time_zone(date, time_zone))
+            }
+
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Date, D::Error>
+                where D: de::Deserializer
+            {
+                deserializer.deserialize_tuple(2, DateVisitor)
+            }
+
+            fn visit_enum<V>(self, visitor: V) -> Result<Date, V::Error>
+                where V: de::EnumVisitor
+            {
+                use serde::de::VariantVisitor;
+
+                let (tag, content) = visitor.visit_variant::<String>()?;
+                match tag.as_str() {
+                    "Date" => content.visit_tuple(2, DateVisitor),
+                    _ => Err(de::Error::invalid_value(de::Unexpected::Str(&tag), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(DATE, DateVisitor)
+    }
+}
+
 /// Value represente AMF type
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
@@ -54,11 +344,7 @@ impl Value {
     /// assert!(!Value::Null.is_number());
     /// ```
     pub fn is_number(&self) -> bool {
-        if let Value::Number(_) = *self {
-            true
-        } else {
-            false
-        }
+        matches!(*self, Value::Number(_))
     }
 
     /// If the `Value` is a Number, returns the associated f64.
@@ -93,11 +379,7 @@ impl Value {
     /// assert!(!Value::Null.is_bool());
     /// ```
     pub fn is_bool(&self) -> bool {
-        if let Value::Bool(_) = *self {
-            true
-        } else {
-            false
-        }
+        matches!(*self, Value::Bool(_))
     }
 
     /// If the `Value` is a Boolean, returns the associated bool.
@@ -139,24 +421,34 @@ impl From<bool> for Value {
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Value::Number(v) => write!(f, "{}", v.to_string()),
-            Value::Bool(v) => write!(f, "{}", v.to_string()),
-            Value::String(ref s) => write!(f, "{}", s.to_string()),
+            Value::Number(v) => write!(f, "{}", v),
+            Value::Bool(v) => write!(f, "{}", v),
+            Value::String(ref s) => write!(f, "{}", s),
+            Value::LongString(ref s) => write!(f, "{}", s),
             Value::Object(ref m) => {
-                let _ = write!(f, "Object{}\n", "{"); //TODO make this clean
+                let _ = writeln!(f, "Object{{");
                 for (k, v) in m {
-                    let _ = write!(f, "{} => {},\n", k, v);
+                    let _ = writeln!(f, "{} => {},", k, v);
                 }
-                write!(f, "{}\n", "}") //TODO make this clean
+                writeln!(f, "}}")
             },
             Value::ECMAArray(ref m) => {
-                let _ = write!(f, "Tab{}\n", "{"); //TODO make this clean
+                let _ = writeln!(f, "Tab{{");
+                for (k, v) in m {
+                    let _ = writeln!(f, "{} => {},", k, v);
+                }
+                write!(f, "}}")
+            },
+            Value::TypedObject(ref name, ref m) => {
+                let _ = writeln!(f, "{}{{", name);
                 for (k, v) in m {
-                    let _ = write!(f, "{} => {},\n", k, v);
+                    let _ = writeln!(f, "{} => {},", k, v);
                 }
-                write!(f, "{}", "}") //TODO make this clean
+                writeln!(f, "}}")
             },
             Value::Null => write!(f, "Null"),
+            Value::Date(ref d) => write!(f, "{}", d),
+            Value::XMLDocument(ref s) => write!(f, "{}", s),
             _ => write!(f, "value")
         }
     }
@@ -167,36 +459,43 @@ impl serde::Serialize for Value {
         match *self {
             Value::Number(v) => serializer.serialize_f64(v),
             Value::Bool(v) => serializer.serialize_bool(v),
-            Value::String(ref v) => serializer.serialize_str(&v),
+            Value::String(ref v) => serializer.serialize_str(v),
             Value::Object(ref m) => {
-                let mut map_serializer = serializer.serialize_map(None).unwrap();
+                let mut map_serializer = serializer.serialize_map(None)?;
                 use serde::ser::SerializeMap;
                 for (k, v) in m {
-                    let _ = map_serializer.serialize_key(k);
-                    let _ = map_serializer.serialize_value(v);
+                    map_serializer.serialize_key(k)?;
+                    map_serializer.serialize_value(v)?;
                 }
                 map_serializer.end()
             },
             Value::Movieclip => serializer.serialize_unit(),
             Value::Null => serializer.serialize_unit(),
-            Value::Undefined => serializer.serialize_unit(),
-            Value::Reference(v) => serializer.serialize_u16(v),
+            Value::Undefined => serializer.serialize_none(),
+            Value::Reference(v) => serializer.serialize_newtype_struct(REFERENCE, &v),
             Value::ECMAArray(ref m) => {
-                let mut map_serializer = serializer.serialize_map(Some(m.len())).unwrap();
+                let mut map_serializer = serializer.serialize_map(Some(m.len()))?;
                 use serde::ser::SerializeMap;
                 for (k, v) in m {
-                    let _ = map_serializer.serialize_key(k);
-                    let _ = map_serializer.serialize_value(v);
+                    map_serializer.serialize_key(k)?;
+                    map_serializer.serialize_value(v)?;
                 }
                 map_serializer.end()
             },
-//            Value::StrictArray(ref m) => serializer.serialize_strict_array(&m),
-            //Value::Date(v) => serializer.serialize_u16(v),
+            Value::StrictArray(ref v) => {
+                let mut seq_serializer = serializer.serialize_seq(Some(v.len()))?;
+                use serde::ser::SerializeSeq;
+                for e in v {
+                    seq_serializer.serialize_element(e)?;
+                }
+                seq_serializer.end()
+            },
+            Value::Date(ref v) => v.serialize(serializer),
+            Value::LongString(ref v) => serializer.serialize_newtype_struct(LONG_STRING, v),
             Value::Unsupported => serializer.serialize_unit(),
             Value::Recordset => serializer.serialize_unit(),
-            Value::XMLDocument(ref v) => serializer.serialize_str(&v),
-            //TypedObject(String, Map<String, Value>),*/
-            _ => serializer.serialize_unit(),
+            Value::XMLDocument(ref v) => serializer.serialize_newtype_struct(XML_DOCUMENT, v),
+            Value::TypedObject(ref name, ref m) => serializer.serialize_newtype_struct(TYPED_OBJECT, &(name, m)),
         }
     }
 }
@@ -243,6 +542,12 @@ impl de::Deserialize for Value {
                 Ok(Value::Undefined)
             }
 
+            fn visit_str<E>(self, value: &str) -> Result<Value, E>
+                where E: de::Error,
+            {
+                Ok(Value::String(value.to_string()))
+            }
+
             fn visit_string<E>(self, value: String) -> Result<Value, E>
                 where E: de::Error,
             {
@@ -254,7 +559,7 @@ impl de::Deserialize for Value {
             {
                 let mut values = Map::new();
 
-                while let Some((key, value)) = try!(visitor.visit()) {
+                while let Some((key, value)) = visitor.visit()? {
                     values.insert(key, value);
                 }
 
@@ -270,14 +575,40 @@ impl de::Deserialize for Value {
             {
                 let mut values = Vec::new();
 
-                while let Some(value) = try!(visitor.visit()) {
+                while let Some(value) = visitor.visit()? {
                     values.push(value);
                 }
                 Ok(Value::StrictArray(values))
             }
+
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Value, D::Error>
+                where D: de::Deserializer
+            {
+                deserializer.deserialize(ValueVisitor)
+            }
+
+            //
+            // AMF types without a serde equivalent come tagged with their name
+            //
+            fn visit_enum<V>(self, visitor: V) -> Result<Value, V::Error>
+                where V: de::EnumVisitor
+            {
+                use serde::de::VariantVisitor;
+
+                let (tag, content) = visitor.visit_variant::<String>()?;
+                match tag.as_str() {
+                    "LongString" => content.visit_newtype().map(Value::LongString),
+                    "Date" => content.visit_newtype().map(Value::Date),
+                    "XMLDocument" => content.visit_newtype().map(Value::XMLDocument),
+                    "TypedObject" => content.visit_newtype().map(|(name, m)| Value::TypedObject(name, m)),
+                    _ => Err(de::Error::unknown_variant(&tag, TAGS)),
+                }
+            }
         }
 
-        deserializer.deserialize(ValueVisitor)
+        const TAGS: &[&str] = &["LongString", "Date", "XMLDocument", "TypedObject"];
+
+        deserializer.deserialize_newtype_struct(VALUE, ValueVisitor)
     }
 }
 
@@ -302,9 +633,12 @@ pub enum Marker {
     TypedObject
 }
 
-impl From<u8> for Marker {
-    fn from(v: u8) -> Marker {
-        match v {
+/// Markers above 0x10 are not part of AMF0, the byte is given back.
+impl TryFrom<u8> for Marker {
+    type Error = u8;
+
+    fn try_from(v: u8) -> Result<Marker, u8> {
+        Ok(match v {
             0x00 => Marker::Number,
             0x01 => Marker::Boolean,
             0x02 => Marker::String,
@@ -322,7 +656,7 @@ impl From<u8> for Marker {
             0x0E => Marker::Recordset,
             0x0F => Marker::XMLDocument,
             0x10 => Marker::TypedObject,
-            _ => Marker::Undefined
-        }
+            _ => return Err(v),
+        })
     }
 }
\ No newline at end of file